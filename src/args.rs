@@ -0,0 +1,183 @@
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use uefi::boot::{self, OpenProtocolAttributes, OpenProtocolParams};
+use uefi::proto::loaded_image::LoadedImage;
+use uefi::proto::shell_params::ShellParameters;
+use crate::config::Config;
+use crate::error::{NyaStatus, Result};
+
+// 长选项、短选项 -> 配置文件里的 (节, 键),值的写法和配置文件一样
+const OPTIONS: &[(&str, Option<char>, &str, &str)] = &[
+    ("source", Some('f'), "video", "source"),
+    ("browse", None, "video", "browse"),
+    ("fps", None, "video", "fps"),
+    ("loop", Some('l'), "video", "loop"),
+    ("renderer", Some('r'), "video", "renderer"),
+    ("cores", Some('c'), "video", "cores"),
+    ("mode", Some('m'), "display", "mode"),
+    ("scale", Some('s'), "display", "scale"),
+    ("filter", None, "display", "filter"),
+    ("border", None, "display", "border"),
+    ("rotation", None, "display", "rotation"),
+    ("hud", None, "display", "hud"),
+    ("font", None, "display", "font"),
+    ("font-scale", None, "display", "font_scale"),
+    ("filters", None, "filters", "chain"),
+    ("chain", None, "boot", "chain"),
+    ("timeout", Some('t'), "boot", "timeout"),
+    ("log-console", None, "log", "console"),
+    ("log-serial", None, "log", "serial"),
+    ("log-file", None, "log", "file"),
+];
+
+// 不带值的开关:长选项、短选项 -> (节, 键, 值)
+const FLAGS: &[(&str, Option<char>, &str, &str, &str)] = &[
+    ("browse", Some('b'), "video", "browse", "on"),
+    ("no-hud", None, "display", "hud", "off"),
+    ("no-filters", None, "filters", "enabled", "off"),
+];
+
+/// 命令行参数,不含程序名
+/// Shell 里启动走 ShellParameters,否则拆 LoadedImage 的启动参数
+pub fn args() -> Vec<String> {
+    let params = OpenProtocolParams { handle: boot::image_handle(), agent: boot::image_handle(), controller: None };
+    if let Ok(shell) = unsafe { boot::open_protocol::<ShellParameters>(params, OpenProtocolAttributes::GetProtocol) } {
+        return shell.args().skip(1).map(|a| a.to_string()).collect();
+    }
+
+    let Ok(loaded) = (unsafe { boot::open_protocol::<LoadedImage>(params, OpenProtocolAttributes::GetProtocol) }) else {
+        return Vec::new();
+    };
+    let Ok(options) = loaded.load_options_as_cstr16() else { return Vec::new() };
+    let mut words = split(&options.to_string());
+    // Shell 和一些启动管理器会把程序自己放在第一个
+    if words.first().is_some_and(|w| w.to_ascii_lowercase().ends_with(".efi")) {
+        words.remove(0);
+    }
+    words
+}
+
+/// 覆盖到 config 上,在读完配置文件之后调用
+/// 支持 --scale fit、--scale=fit、-s fit 和 --browse 这种开关,单独一个不带 - 的参数当作 source
+pub fn apply(config: &mut Config, args: &[String]) -> Result {
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let err = |msg: String| NyaStatus::_Debug(format!("argument '{}': {}", arg, msg));
+
+        let (name, inline) = if let Some(long) = arg.strip_prefix("--") {
+            match long.split_once('=') {
+                Some((name, value)) => (name, Some(value)),
+                None => (long, None),
+            }
+        } else if let Some(short) = arg.strip_prefix('-').filter(|s| s.chars().count() == 1) {
+            let c = short.chars().next().unwrap_or_default();
+            let option = OPTIONS.iter().find(|o| o.1 == Some(c)).map(|o| o.0);
+            match option.or_else(|| FLAGS.iter().find(|f| f.1 == Some(c)).map(|f| f.0)) {
+                Some(name) => (name, None),
+                None => return Err(err("unknown option".to_string())),
+            }
+        } else {
+            config.set("video", "source", arg).map_err(err)?;
+            continue;
+        };
+
+        if let Some(&(_, _, section, key, value)) = FLAGS.iter().find(|f| f.0.eq_ignore_ascii_case(name)).filter(|_| inline.is_none()) {
+            config.set(section, key, value).map_err(err)?;
+            continue;
+        }
+
+        let Some(&(_, _, section, key)) = OPTIONS.iter().find(|o| o.0.eq_ignore_ascii_case(name)) else {
+            return Err(err("unknown option".to_string()));
+        };
+        let value = match inline {
+            Some(value) => value,
+            None => iter.next().ok_or_else(|| err("missing value".to_string()))?,
+        };
+        config.set(section, key, value.trim()).map_err(err)?;
+    }
+    Ok(())
+}
+
+// 按空白拆开,双引号里的空白不拆
+fn split(line: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let (mut quoted, mut started) = (false, false);
+    for c in line.chars() {
+        match c {
+            '"' => { quoted = !quoted; started = true }
+            c if c.is_whitespace() && !quoted => {
+                if started { words.push(core::mem::take(&mut word)) }
+                started = false;
+            }
+            // 启动项的参数可能带着结尾的 0
+            '\0' => {}
+            c => { word.push(c); started = true }
+        }
+    }
+    if started { words.push(word) }
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graphics::scale::ScaleMode;
+
+    fn parse(line: &str) -> Result<Config> {
+        let mut config = Config::default();
+        apply(&mut config, &split(line))?;
+        Ok(config)
+    }
+
+    #[test]
+    fn quotes_keep_spaces() {
+        assert_eq!(split("  -f \"my videos\\a b.qois\"  --fps 30\0"), ["-f", "my videos\\a b.qois", "--fps", "30"]);
+        // 空的引号也是一个参数
+        assert_eq!(split("--font \"\""), ["--font", ""]);
+    }
+
+    #[test]
+    fn long_option_with_and_without_equals() {
+        let config = parse("--scale=stretch --fps 24").unwrap();
+        assert_eq!(config.scale, ScaleMode::Stretch);
+        assert_eq!(config.fps, 24);
+        // 开关不接受 =value,当普通选项找不到
+        assert!(parse("--no-hud=on").is_err());
+    }
+
+    #[test]
+    fn short_options_and_flags() {
+        let config = parse("-s integer -b -t 5").unwrap();
+        assert_eq!(config.scale, ScaleMode::Integer);
+        assert!(config.browse);
+        assert_eq!(config.splash_timeout, 5);
+    }
+
+    #[test]
+    fn bare_argument_is_source() {
+        let config = parse("--no-hud clips\\intro.qois").unwrap();
+        assert_eq!(config.source, "clips\\intro.qois");
+        assert!(!config.hud);
+    }
+
+    #[test]
+    fn command_line_overrides_config() {
+        let mut config = Config::default();
+        // 配置文件里读到的值
+        config.set("display", "scale", "fill").unwrap();
+        config.set("video", "fps", "60").unwrap();
+        apply(&mut config, &split("-s none")).unwrap();
+        assert_eq!(config.scale, ScaleMode::None);
+        assert_eq!(config.fps, 60);
+    }
+
+    #[test]
+    fn unknown_and_incomplete_options_fail() {
+        assert!(parse("--volume 3").is_err());
+        assert!(parse("-x").is_err());
+        assert!(parse("--fps").is_err());
+        assert!(parse("--scale sideways").is_err());
+    }
+}
@@ -0,0 +1,233 @@
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use uefi::proto::console::gop::BltPixel;
+use uefi::proto::console::text::{Key, ScanCode};
+use crate::error::Result;
+use crate::fs::{join, to_path, Fs};
+use crate::graphics::font;
+use crate::graphics::{console, Screen};
+use crate::input::wait_key;
+use crate::video::decoder::probe_resolution;
+use crate::video::playlist::{is_playlist, is_video};
+
+// 名字以外的列:大小 10 + 格式 16 + 间隔
+const INFO_COLS: usize = 30;
+
+const BG: BltPixel = BltPixel::new(0, 0, 0);
+const FG: BltPixel = BltPixel::new(220, 220, 220);
+const DIR_FG: BltPixel = BltPixel::new(100, 180, 255);
+const BAR_BG: BltPixel = BltPixel::new(40, 40, 60);
+const SEL_BG: BltPixel = BltPixel::new(0, 120, 215);
+const SEL_FG: BltPixel = BltPixel::new(255, 255, 255);
+
+const HELP: &str = "Up/Down select  Enter open/play  Left/Backspace up  Esc quit";
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Volume,
+    Dir,
+    File,
+}
+
+// 列表里的一项
+struct Item {
+    // 显示的名字
+    name: String,
+    // 打开时用的完整路径
+    path: String,
+    kind: Kind,
+    size: u64,
+    // 文件是探测出来的格式,卷是卷标,目录为空
+    format: String,
+}
+
+/// 文本界面的文件浏览器,选中视频或播放列表后把路径交给调用方去播
+pub struct Browser {
+    // 当前目录,空串为本程序所在卷的根目录,可以带 fsN: 前缀,None 为卷列表
+    cwd: Option<String>,
+    items: Vec<Item>,
+    selected: usize,
+    // 列表第一行显示的项
+    top: usize,
+    /// 底栏的消息,比如上一次播放出错,空的时候显示按键帮助
+    pub status: String,
+}
+
+impl Browser {
+    pub fn new(fs: &mut Fs, dir: &str) -> Result<Self> {
+        let mut browser = Self { cwd: None, items: Vec::new(), selected: 0, top: 0, status: String::new() };
+        browser.open(fs, dir)?;
+        Ok(browser)
+    }
+
+    /// 显示列表并处理按键,选中文件时返回它的路径,Esc 返回 None
+    /// 本卷根目录再往上是所有卷的列表
+    pub fn run(&mut self, fs: &mut Fs, screen: &mut Screen) -> Result<Option<String>> {
+        screen.clear()?;
+        loop {
+            let page = (screen.resolution().1 / console::cell().1).saturating_sub(2).max(1);
+            self.draw(screen, page)?;
+
+            let Some(key) = wait_key() else { return Ok(None) };
+            match key {
+                Key::Special(ScanCode::UP) => self.select(self.selected as isize - 1),
+                Key::Special(ScanCode::DOWN) => self.select(self.selected as isize + 1),
+                Key::Special(ScanCode::PAGE_UP) => self.select(self.selected as isize - page as isize),
+                Key::Special(ScanCode::PAGE_DOWN) => self.select(self.selected as isize + page as isize),
+                Key::Special(ScanCode::HOME) => self.select(0),
+                Key::Special(ScanCode::END) => self.select(isize::MAX),
+                Key::Special(ScanCode::LEFT) => self.leave(fs),
+                Key::Special(ScanCode::ESCAPE) => return Ok(None),
+                Key::Special(ScanCode::RIGHT) => if let Some(path) = self.activate(fs) { return Ok(Some(path)) },
+                Key::Printable(c) => match char::from(c) {
+                    '\r' => if let Some(path) = self.activate(fs) { return Ok(Some(path)) },
+                    '\u{8}' => self.leave(fs),
+                    'q' | 'Q' => return Ok(None),
+                    _ => {}
+                },
+                _ => {}
+            }
+        }
+    }
+
+    // 目录和卷进去,文件返回路径
+    fn activate(&mut self, fs: &mut Fs) -> Option<String> {
+        let item = self.items.get(self.selected)?;
+        let path = item.path.clone();
+        if item.kind == Kind::File { return Some(path) }
+        if let Err(e) = self.open(fs, &path) { self.status = format!("{}: {:?}", path, e) }
+        None
+    }
+
+    // 回到上一级,卷的根目录再往上是卷列表,回来以后选中刚才的目录
+    fn leave(&mut self, fs: &mut Fs) {
+        let Some(cwd) = self.cwd.take() else { return };
+        if cwd.is_empty() || cwd.ends_with(':') {
+            self.show_volumes(fs);
+        } else {
+            let parent = cwd.rfind('\\').map_or("", |i| &cwd[..i]);
+            if let Err(e) = self.open(fs, parent) {
+                self.status = format!("{}: {:?}", parent, e);
+                self.cwd = Some(cwd);
+                return;
+            }
+        }
+        self.selected = self.items.iter().position(|i| i.path == cwd).unwrap_or(0);
+    }
+
+    fn show_volumes(&mut self, fs: &Fs) {
+        self.items = fs.volumes.iter().enumerate().map(|(i, v)| Item {
+            name: format!("fs{}: {}", i, v.device),
+            path: format!("fs{}:", i),
+            kind: Kind::Volume,
+            size: v.size,
+            format: if v.label.is_empty() { String::from("(no label)") } else { v.label.clone() },
+        }).collect();
+        self.cwd = None;
+        self.selected = 0;
+        self.top = 0;
+    }
+
+    // 目录在前,都按名字排,大小写不敏感,文件只留能播的
+    fn open(&mut self, fs: &mut Fs, dir: &str) -> Result {
+        let mut entries = fs.list(&to_path(dir)?)?;
+        entries.retain(|e| e.is_dir || is_video(&e.name) || is_playlist(&e.name));
+        entries.sort_by_key(|e| (!e.is_dir, e.name.to_ascii_lowercase()));
+
+        self.items = entries.into_iter().map(|e| {
+            let path = join(dir, &e.name);
+            let (kind, format) = if e.is_dir { (Kind::Dir, String::new()) } else { (Kind::File, detect(fs, &path, &e.name)) };
+            Item { name: e.name, path, kind, size: e.size, format }
+        }).collect();
+        self.cwd = Some(dir.to_string());
+        self.selected = 0;
+        self.top = 0;
+        Ok(())
+    }
+
+    fn select(&mut self, index: isize) {
+        let last = self.items.len().saturating_sub(1) as isize;
+        self.selected = index.clamp(0, last) as usize;
+    }
+
+    // 每次按键整屏重画,一行一次 blt
+    fn draw(&mut self, screen: &mut Screen, page: usize) -> Result {
+        let (width, height) = screen.resolution();
+        // 和控制台一样的字符格,字高加行距
+        let (cw, line) = console::cell();
+        let cols = width / cw;
+        // 选中项滚出去了就跟着滚
+        if self.selected < self.top { self.top = self.selected }
+        if self.selected >= self.top + page { self.top = self.selected + 1 - page }
+
+        let title = match &self.cwd {
+            None => String::from(" uefi-player  volumes"),
+            Some(cwd) if cwd.contains(':') => format!(" uefi-player  {}", cwd),
+            Some(cwd) => format!(" uefi-player  \\{}", cwd),
+        };
+        screen.draw_text((0, 0), width, &title, SEL_FG, BAR_BG)?;
+
+        let name_cols = cols.saturating_sub(INFO_COLS + 2).max(8);
+        for row in 0..page {
+            let y = (row + 1) * line;
+            let Some(item) = self.items.get(self.top + row) else {
+                let text = if row == 0 && self.items.is_empty() { " (no media here)" } else { "" };
+                screen.draw_text((0, y), width, text, FG, BG)?;
+                continue;
+            };
+
+            let mut name = item.name.clone();
+            if item.kind == Kind::Dir { name.push('\\') }
+            let name = clip(&name, name_cols);
+            let size = if item.kind == Kind::Dir { String::from("<DIR>") } else { human_size(item.size) };
+            let text = format!(" {} {:>10}  {:<16}", name, size, item.format);
+
+            let (fg, bg) = match (self.top + row == self.selected, item.kind) {
+                (true, _) => (SEL_FG, SEL_BG),
+                (false, Kind::File) => (FG, BG),
+                (false, _) => (DIR_FG, BG),
+            };
+            screen.draw_text((0, y), width, &text, fg, bg)?;
+        }
+
+        let footer = if self.status.is_empty() { HELP } else { &self.status };
+        let footer = format!(" {}", footer);
+        screen.draw_text((0, height.saturating_sub(line)), width, &footer, SEL_FG, BAR_BG)
+    }
+}
+
+// 列表只按扩展名筛,这里读文件头确认一下
+fn detect(fs: &mut Fs, path: &str, name: &str) -> String {
+    if is_playlist(name) { return String::from("M3U playlist") }
+    let probe = to_path(path)
+        .and_then(|path| fs.open_file(&path))
+        .and_then(|mut file| probe_resolution(&mut file));
+    match probe {
+        Ok((w, h)) => format!("QOIS {}x{}", w, h),
+        Err(_) => String::from("unreadable"),
+    }
+}
+
+// 按显示宽度补齐到 cols 格,太长的名字截掉,末尾换成 ~
+fn clip(s: &str, cols: usize) -> String {
+    let mut clipped = String::from(s);
+    if font::text_width(s) > cols {
+        clipped = String::from(font::clip(s, cols.saturating_sub(1)));
+        clipped.push('~');
+    }
+    let pad = cols.saturating_sub(font::text_width(&clipped));
+    clipped.extend(core::iter::repeat_n(' ', pad));
+    clipped
+}
+
+// 1024 进制,保留一位小数
+fn human_size(size: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let (mut tenths, mut unit) = (size * 10, 0);
+    while tenths >= 10240 && unit < UNITS.len() - 1 {
+        tenths /= 1024;
+        unit += 1;
+    }
+    if unit == 0 { format!("{} B", size) } else { format!("{}.{} {}", tenths / 10, tenths % 10, UNITS[unit]) }
+}
@@ -0,0 +1,124 @@
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use uefi::boot::{self, LoadImageSource, OpenProtocolAttributes, OpenProtocolParams};
+use uefi::proto::BootPolicy;
+use uefi::proto::device_path::build::{self, DevicePathBuilder};
+use uefi::proto::device_path::DevicePath;
+use uefi::proto::loaded_image::LoadedImage;
+use uefi::runtime::{self, VariableVendor};
+use uefi::{CString16, Handle, Status};
+use crate::error::{NyaStatus, Result};
+use crate::fs::to_path;
+
+/// 开机动画播完以后要启动的东西
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Target {
+    /// 和本程序同一个卷上的 EFI 文件
+    File(String),
+    /// 固件启动项 BootXXXX,十六进制编号
+    BootOption(u16),
+}
+
+impl Target {
+    /// Boot0003 这种写法当启动项,其余都当路径
+    pub fn parse(s: &str) -> Self {
+        let s = s.trim();
+        // 用户写的,可能有非 ASCII,按字节切要先确认在字符边界上
+        if s.len() == 8 && s.get(..4).is_some_and(|p| p.eq_ignore_ascii_case("boot")) && let Some(Ok(n)) = s.get(4..).map(|n| u16::from_str_radix(n, 16)) {
+            return Target::BootOption(n);
+        }
+        Target::File(String::from(s))
+    }
+}
+
+/// 加载并启动 target,返回它退出时的状态
+/// 调用前必须已经释放独占的 GOP,否则下一个程序打不开显卡
+pub fn start(target: &Target) -> Result<Status> {
+    match target {
+        Target::File(path) => {
+            let mut buf = Vec::new();
+            let device_path = file_device_path(path, &mut buf)?;
+            run(load(device_path, BootPolicy::ExactMatch)?)
+        }
+        Target::BootOption(n) => {
+            let name = CString16::try_from(format!("Boot{:04X}", n).as_str()).map_err(|_| NyaStatus::FromStrWithBufError)?;
+            let (var, _) = runtime::get_variable_boxed(&name, &VariableVendor::GLOBAL_VARIABLE)?;
+            let (device_path, options) = parse_load_option(&var).ok_or(Status::VOLUME_CORRUPTED)?;
+            let image = load(device_path, BootPolicy::BootSelection)?;
+            // 启动项自带的参数原样传下去,var 要活到 start_image 返回
+            if !options.is_empty() {
+                let mut loaded = unsafe { boot::open_protocol::<LoadedImage>(params(image), OpenProtocolAttributes::GetProtocol) }?;
+                unsafe { loaded.set_load_options(options.as_ptr(), options.len() as u32) }
+            }
+            run(image)
+        }
+    }
+}
+
+// 只借用不独占,独占会把挂在设备上的驱动断开
+fn params(handle: Handle) -> OpenProtocolParams {
+    OpenProtocolParams { handle, agent: boot::image_handle(), controller: None }
+}
+
+fn load(device_path: &DevicePath, boot_policy: BootPolicy) -> Result<Handle> {
+    Ok(boot::load_image(boot::image_handle(), LoadImageSource::FromDevicePath { device_path, boot_policy })?)
+}
+
+// 子程序的退出码原样交回去,不算我们出错
+// 启动失败时镜像还留在内存里,要自己卸掉
+fn run(image: Handle) -> Result<Status> {
+    Ok(match boot::start_image(image) {
+        Ok(()) => Status::SUCCESS,
+        Err(e) => {
+            let _ = boot::unload_image(image);
+            e.status()
+        }
+    })
+}
+
+// 本程序所在设备的路径后面接上文件路径,下一个程序能靠它找到自己的卷
+fn file_device_path<'a>(path: &str, buf: &'a mut Vec<u8>) -> Result<&'a DevicePath> {
+    let loaded = unsafe { boot::open_protocol::<LoadedImage>(params(boot::image_handle()), OpenProtocolAttributes::GetProtocol) }?;
+    let device = loaded.device().ok_or(Status::NOT_FOUND)?;
+    let device_path = unsafe { boot::open_protocol::<DevicePath>(params(device), OpenProtocolAttributes::GetProtocol) }?;
+    let file = to_path(path)?;
+
+    let build_err = |_| NyaStatus::from(Status::BUFFER_TOO_SMALL);
+    let mut builder = DevicePathBuilder::with_vec(buf);
+    for node in device_path.node_iter() {
+        builder = builder.push(&node).map_err(build_err)?;
+    }
+    builder.push(&build::media::FilePath { path_name: &file }).map_err(build_err)?.finalize().map_err(build_err)
+}
+
+// EFI_LOAD_OPTION:属性 u32,路径长度 u16,以 0 结尾的 UCS-2 描述,设备路径,剩下的是参数
+// source: UEFI Spec 2.10 3.1.3
+fn parse_load_option(var: &[u8]) -> Option<(&DevicePath, &[u8])> {
+    let path_len = u16::from_le_bytes(var.get(4..6)?.try_into().ok()?) as usize;
+    let mut i = 6;
+    while var.get(i..i + 2)? != [0, 0] { i += 2 }
+    i += 2;
+    let device_path = <&DevicePath>::try_from(var.get(i..i + path_len)?).ok()?;
+    Some((device_path, &var[i + path_len..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn boot_option() {
+        assert_eq!(Target::parse("Boot0003"), Target::BootOption(3));
+        assert_eq!(Target::parse(" boot00A1 "), Target::BootOption(0xA1));
+        assert_eq!(Target::parse("Boot00G1"), Target::File(String::from("Boot00G1")));
+    }
+
+    // 第 4 个字节落在多字节字符中间时不能 panic
+    #[test]
+    fn non_ascii_is_a_path() {
+        assert_eq!(Target::parse("bö01"), Target::File(String::from("bö01")));
+        assert_eq!(Target::parse("abcé123"), Target::File(String::from("abcé123")));
+        assert_eq!(Target::parse("Bootö01"), Target::File(String::from("Bootö01")));
+    }
+}
@@ -0,0 +1,261 @@
+use core::slice;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use core::time::Duration;
+use raw_cpuid::CpuId;
+use uefi::boot;
+use uefi::system::with_config_table;
+use uefi::table::cfg::ConfigTableEntry as cfg;
+
+// ACPI 表头 36 字节,之后是各表自己的内容
+// source: ACPI 6.5 5.2.6 / 5.2.9 FADT / IA-PC HPET Specification 1.0a
+const SDT_HEADER_LEN: usize = 36;
+// ACPI PM 定时器固定 3.579545 MHz
+const PM_TIMER_HZ: u64 = 3_579_545;
+// HPET 寄存器偏移
+const HPET_CAPS: usize = 0x00;
+const HPET_CONFIG: usize = 0x10;
+const HPET_COUNTER: usize = 0xF0;
+// 校准时长,太短误差大,太长启动慢
+const CALIBRATE_NS: u64 = 50_000_000;
+
+/// 用来校准 TSC 的硬件定时器
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClockSource {
+    /// HPET 主计数器,base 为 MMIO 地址
+    Hpet { base: u64, period_fs: u32 },
+    /// ACPI PM 定时器,24 或 32 位
+    PmTimer { port: PmPort, bits: u32 },
+    /// 找不到硬件定时器,退回 boot::stall
+    Stall,
+}
+
+/// PM 定时器所在地址空间
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PmPort {
+    Io(u16),
+    Memory(u64),
+}
+
+// 所有核心共用的时钟参数,只在 BSP 上 init 一次,之后只读
+static TSC_HZ: AtomicU64 = AtomicU64::new(0);
+static TSC_BASE: AtomicU64 = AtomicU64::new(0);
+static HPET_BASE: AtomicU64 = AtomicU64::new(0);
+static HPET_PERIOD_FS: AtomicU64 = AtomicU64::new(0);
+static HPET_START: AtomicU64 = AtomicU64::new(0);
+// TSC 不恒定时改读 HPET
+static USE_HPET: AtomicBool = AtomicBool::new(false);
+// HPET 是我们开的,退出时要关回去,存 MMIO 地址,0 为不用管
+static HPET_ENABLED_BY_US: AtomicU64 = AtomicU64::new(0);
+
+/// 找定时器、校准 TSC,必须在 BSP 上、启动 AP 之前调用
+pub fn init() -> ClockSource {
+    let source = find_source();
+    let invariant = has_invariant_tsc();
+
+    let hz = calibrate(source);
+    TSC_HZ.store(hz, Ordering::Relaxed);
+    TSC_BASE.store(rdtsc(), Ordering::Relaxed);
+    if let ClockSource::Hpet { base, period_fs } = source {
+        HPET_BASE.store(base, Ordering::Relaxed);
+        HPET_PERIOD_FS.store(period_fs as u64, Ordering::Relaxed);
+        HPET_START.store(unsafe { hpet_read(base, HPET_COUNTER) }, Ordering::Relaxed);
+        USE_HPET.store(!invariant, Ordering::Relaxed);
+    }
+
+    log::info!("clock: {:?}, TSC {} Hz, invariant: {}", source, hz, invariant);
+    if !invariant && !USE_HPET.load(Ordering::Relaxed) {
+        log::warn!("TSC is not invariant and no HPET, frame timing may drift");
+    }
+    source
+}
+
+/// 把 init 改过的硬件状态还原成固件原来的样子,交回固件或者启动下一个程序之前调用
+/// 之后 now_ns 改用 TSC
+pub fn restore() {
+    let base = HPET_ENABLED_BY_US.swap(0, Ordering::Relaxed);
+    if base == 0 { return }
+    USE_HPET.store(false, Ordering::Relaxed);
+    unsafe {
+        let config = hpet_read(base, HPET_CONFIG);
+        ((base as usize + HPET_CONFIG) as *mut u64).write_volatile(config & !1);
+    }
+}
+
+/// init 校准过了,之前 now_ns 的值没有意义
+pub fn ready() -> bool {
+    TSC_HZ.load(Ordering::Relaxed) != 0
+}
+
+/// 单调递增的纳秒时钟,从 init 开始计,任何核心都能调
+#[inline]
+pub fn now_ns() -> u64 {
+    if USE_HPET.load(Ordering::Relaxed) {
+        let base = HPET_BASE.load(Ordering::Relaxed);
+        let ticks = unsafe { hpet_read(base, HPET_COUNTER) }.wrapping_sub(HPET_START.load(Ordering::Relaxed));
+        return (ticks as u128 * HPET_PERIOD_FS.load(Ordering::Relaxed) as u128 / 1_000_000) as u64;
+    }
+    let hz = TSC_HZ.load(Ordering::Relaxed).max(1);
+    let ticks = rdtsc().wrapping_sub(TSC_BASE.load(Ordering::Relaxed));
+    (ticks as u128 * 1_000_000_000 / hz as u128) as u64
+}
+
+/// CPUID.80000007h:EDX[8],频率变化和深度睡眠时 TSC 也匀速走
+pub fn has_invariant_tsc() -> bool {
+    CpuId::new().get_advanced_power_mgmt_info().is_some_and(|info| info.has_invariant_tsc())
+}
+
+#[inline(always)]
+fn rdtsc() -> u64 { unsafe { core::arch::x86_64::_rdtsc() } }
+
+// HPET 优先,精度高;只用 64 位的,32 位的几分钟就回绕,交给 PM 定时器
+fn find_source() -> ClockSource {
+    let Some(rsdp) = find_rsdp() else { return ClockSource::Stall };
+    let hpet = unsafe { find_table(rsdp, b"HPET") }.and_then(|t| unsafe { parse_hpet(t) });
+    if let Some(hpet) = hpet { return hpet }
+    unsafe { find_table(rsdp, b"FACP") }.and_then(|t| unsafe { parse_fadt(t) }).unwrap_or(ClockSource::Stall)
+}
+
+// 和 get_smbios 一样从配置表里找,ACPI 2.0 优先
+fn find_rsdp() -> Option<u64> {
+    with_config_table(|slice| {
+        let mut address = None;
+        for i in slice {
+            match i.guid {
+                cfg::ACPI2_GUID => { address = Some(i.address as u64); break; }
+                cfg::ACPI_GUID => address = Some(i.address as u64),
+                _ => {}
+            }
+        }
+        address
+    })
+}
+
+// RSDP:rev 在 15,RSDT 在 16(u32),XSDT 在 24(u64,rev >= 2)
+unsafe fn find_table(rsdp: u64, signature: &[u8; 4]) -> Option<*const u8> {
+    unsafe {
+        let p = rsdp as *const u8;
+        if slice::from_raw_parts(p, 8) != b"RSD PTR " { return None }
+        let revision = p.add(15).read();
+        let xsdt = (p.add(24) as *const u64).read_unaligned();
+        let (root, entry_len) = if revision >= 2 && xsdt != 0 {
+            (xsdt as *const u8, 8)
+        } else {
+            ((p.add(16) as *const u32).read_unaligned() as u64 as *const u8, 4)
+        };
+
+        let len = (root.add(4) as *const u32).read_unaligned() as usize;
+        let count = len.saturating_sub(SDT_HEADER_LEN) / entry_len;
+        for i in 0..count {
+            let entry = root.add(SDT_HEADER_LEN + i * entry_len);
+            let table = match entry_len {
+                8 => (entry as *const u64).read_unaligned(),
+                _ => (entry as *const u32).read_unaligned() as u64,
+            } as *const u8;
+            if !table.is_null() && slice::from_raw_parts(table, 4) == signature {
+                return Some(table);
+            }
+        }
+        None
+    }
+}
+
+// HPET 表:基址的 GAS 在 40,地址在 44;GAS 第一个字节是地址空间,0 为内存
+unsafe fn parse_hpet(table: *const u8) -> Option<ClockSource> {
+    unsafe {
+        if table.add(40).read() != 0 { return None }
+        let base = (table.add(44) as *const u64).read_unaligned();
+        if base == 0 { return None }
+        // 能力寄存器高 32 位是计数周期(飞秒),规范要求不超过 100ns
+        // 第 13 位 COUNT_SIZE_CAP 为 0 是 32 位计数器,14.3 MHz 下 5 分钟就回绕,不用
+        let caps = hpet_read(base, HPET_CAPS);
+        let period_fs = (caps >> 32) as u32;
+        if period_fs == 0 || period_fs > 100_000_000 { return None }
+        if caps & (1 << 13) == 0 { return None }
+        // 固件没开就自己开,启动阶段没有别人在用,退出时 restore 关回去
+        let config = hpet_read(base, HPET_CONFIG);
+        if config & 1 == 0 {
+            ((base as usize + HPET_CONFIG) as *mut u64).write_volatile(config | 1);
+            HPET_ENABLED_BY_US.store(base, Ordering::Relaxed);
+        }
+        Some(ClockSource::Hpet { base, period_fs })
+    }
+}
+
+// FADT:PM_TMR_BLK 在 76,FLAGS 在 112(bit 8 为 32 位计数),X_PM_TMR_BLK 在 208
+unsafe fn parse_fadt(table: *const u8) -> Option<ClockSource> {
+    unsafe {
+        let len = (table.add(4) as *const u32).read_unaligned() as usize;
+        let flags = if len >= 116 { (table.add(112) as *const u32).read_unaligned() } else { 0 };
+        let bits = if flags & (1 << 8) != 0 { 32 } else { 24 };
+
+        if len >= 220 {
+            let space = table.add(208).read();
+            let address = (table.add(208 + 4) as *const u64).read_unaligned();
+            match (space, address) {
+                (_, 0) => {}
+                (0, addr) => return Some(ClockSource::PmTimer { port: PmPort::Memory(addr), bits }),
+                (1, addr) => return Some(ClockSource::PmTimer { port: PmPort::Io(addr as u16), bits }),
+                _ => {}
+            }
+        }
+        match (table.add(76) as *const u32).read_unaligned() {
+            0 => None,
+            port => Some(ClockSource::PmTimer { port: PmPort::Io(port as u16), bits }),
+        }
+    }
+}
+
+#[inline(always)]
+unsafe fn hpet_read(base: u64, offset: usize) -> u64 {
+    unsafe { ((base as usize + offset) as *const u64).read_volatile() }
+}
+
+unsafe fn pm_read(port: PmPort) -> u32 {
+    match port {
+        PmPort::Memory(addr) => unsafe { (addr as *const u32).read_volatile() },
+        PmPort::Io(port) => {
+            let value: u32;
+            unsafe { core::arch::asm!("in eax, dx", out("eax") value, in("dx") port, options(nomem, nostack, preserves_flags)) }
+            value
+        }
+    }
+}
+
+// 忙等硬件定时器走过 CALIBRATE_NS,同时数 TSC
+fn calibrate(source: ClockSource) -> u64 {
+    match source {
+        ClockSource::Hpet { base, period_fs } => {
+            let target = CALIBRATE_NS * 1_000_000 / period_fs as u64;
+            let (t0, c0) = (rdtsc(), unsafe { hpet_read(base, HPET_COUNTER) });
+            let mut elapsed;
+            loop {
+                elapsed = unsafe { hpet_read(base, HPET_COUNTER) }.wrapping_sub(c0);
+                if elapsed >= target { break }
+                core::hint::spin_loop();
+            }
+            let ticks = rdtsc() - t0;
+            (ticks as u128 * 1_000_000_000_000_000 / (elapsed as u128 * period_fs as u128)) as u64
+        }
+        ClockSource::PmTimer { port, bits } => {
+            // 24 位计数器 4.7 秒就回绕,每次只取差值
+            let mask = if bits == 32 { u32::MAX } else { 0xFF_FFFF };
+            let target = CALIBRATE_NS * PM_TIMER_HZ / 1_000_000_000;
+            let t0 = rdtsc();
+            let mut last = unsafe { pm_read(port) } & mask;
+            let mut elapsed = 0u64;
+            while elapsed < target {
+                let now = unsafe { pm_read(port) } & mask;
+                elapsed += (now.wrapping_sub(last) & mask) as u64;
+                last = now;
+                core::hint::spin_loop();
+            }
+            let ticks = rdtsc() - t0;
+            (ticks as u128 * PM_TIMER_HZ as u128 / elapsed as u128) as u64
+        }
+        ClockSource::Stall => {
+            let start = rdtsc();
+            boot::stall(Duration::from_nanos(CALIBRATE_NS));
+            (rdtsc() - start) * (1_000_000_000 / CALIBRATE_NS)
+        }
+    }
+}
@@ -0,0 +1,258 @@
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use log::LevelFilter;
+use uefi::{cstr16, CStr16, Status};
+use crate::error::{NyaStatus, Result};
+use crate::fs::Fs;
+use crate::graphics::filter::ColorFilter;
+use crate::graphics::rotate::Rotation;
+use crate::graphics::scale::{ScaleFilter, ScaleMode};
+use crate::video::Renderer;
+use crate::video::looping::LoopMode;
+
+// 配置文件示例，文档和测试共用一份
+macro_rules! example {
+    () => {
+r"[video]
+source = 1080p\video.qois   ; 视频、目录、.m3u，或者 tftp://10.0.2.2/video.qois
+browse = off                ; 先进文件浏览器
+fps = 60
+loop = forever              ; once / 3 / forever / pingpong / hold
+renderer = multicore        ; multicore / stream / memory / direct
+cores = 0                   ; 0 为全部
+[display]
+mode = auto                 ; 或 1920x1080
+scale = fit
+filter = bilinear
+border = #000000
+rotation = 0
+hud = on
+font = \fonts\ter-u16n.psf   ; PSF2 或 BDF，不写用内置的 8x16
+font_scale = auto           ; 字放大几倍，auto 按屏幕大小挑
+[filters]
+enabled = on
+chain = gamma:1.2, contrast:1.1
+[boot]
+chain = \EFI\BOOT\grubx64.efi  ; 或 Boot0001，写了就是开机动画模式
+timeout = 10
+[log]
+console = warn              ; 播放时叠在画面上；off / error / warn / info / debug / trace
+serial = info
+file = off                  ; 追加到 \uefi-player.log
+"
+    };
+}
+
+#[doc = concat!("配置文件，放在本程序所在卷的根目录\n\n```ini\n", example!(), "```")]
+pub const CONFIG_PATH: &CStr16 = cstr16!("\\uefi-player.ini");
+
+/// 运行参数
+/// 默认值之上由配置文件覆盖
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// 播放源：单个视频、目录或者 .m3u 播放列表，相对卷根目录
+    pub source: String,
+    /// 启动时先进文件浏览器，选中的文件替代 source
+    pub browse: bool,
+    /// 强制使用的显示分辨率，None 为自动挑选
+    pub mode: Option<(usize, usize)>,
+    /// 画面缩放方式
+    pub scale: ScaleMode,
+    pub filter: ScaleFilter,
+    /// 补边颜色 0xRRGGBB
+    pub border: u32,
+    /// 面板安装方向
+    pub rotation: Rotation,
+    /// 调色滤镜，按顺序执行
+    pub filters: Vec<ColorFilter>,
+    /// 启动时滤镜是否生效，运行中可以切换
+    pub filters_enabled: bool,
+    /// 播到结尾以后怎么办
+    pub loop_mode: LoopMode,
+    /// 视频帧率，按秒跳转时换算帧数用
+    pub fps: usize,
+    /// 画法，多核以外的几种没有 OSD 和变速
+    pub renderer: Renderer,
+    /// 最多用几个核心，0 为全部
+    pub cores: usize,
+    /// 左上角的统计信息，开机动画模式下总是关闭
+    pub hud: bool,
+    /// 控制台和 OSD 用的点阵字体，卷内路径，None 为内置字体
+    pub font: Option<String>,
+    /// 字的整数放大倍数，0 为按屏幕大小自动挑
+    pub font_scale: usize,
+    /// 开机动画模式：播完以后启动的 EFI 程序，卷内路径或者 BootXXXX
+    /// None 为普通播放器
+    pub chain: Option<String>,
+    /// 开机动画最长播多久，秒，0 为不限
+    pub splash_timeout: u32,
+    /// 日志各个去处的级别：播放画面上的叠加层、串口、ESP 上的日志文件
+    pub log_console: LevelFilter,
+    pub log_serial: LevelFilter,
+    pub log_file: LevelFilter,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            source: "1080p\\video.qois".to_string(),
+            browse: false,
+            mode: None,
+            scale: ScaleMode::Fit,
+            filter: ScaleFilter::Bilinear,
+            border: 0x000000,
+            rotation: Rotation::Deg0,
+            filters: Vec::new(),
+            filters_enabled: true,
+            loop_mode: LoopMode::Forever,
+            fps: 60,
+            renderer: Renderer::Multicore,
+            cores: 0,
+            hud: true,
+            font: None,
+            font_scale: 0,
+            chain: None,
+            splash_timeout: 0,
+            log_console: LevelFilter::Warn,
+            log_serial: LevelFilter::Info,
+            log_file: LevelFilter::Off,
+        }
+    }
+}
+
+impl Config {
+    /// 读配置文件，没有就用默认值，写错了报出行号
+    pub fn load(fs: &mut Fs) -> Result<Self> {
+        let mut config = Self::default();
+        let data = match fs.read_file(CONFIG_PATH) {
+            Ok(data) => data,
+            Err(NyaStatus::Uefi(e)) if e.status() == Status::NOT_FOUND => return Ok(config),
+            Err(e) => return Err(e),
+        };
+        let text = core::str::from_utf8(&data)
+            .map_err(|_| NyaStatus::_Debug(format!("{}: not valid UTF-8", CONFIG_PATH)))?;
+        config.parse_ini(text)
+            .map_err(|(no, msg)| NyaStatus::_Debug(format!("{}:{}: {}", CONFIG_PATH, no, msg)))?;
+        Ok(config)
+    }
+
+    // INI：[节] 和 键 = 值，; 或 # 开头是注释，值后面空白再跟 ; 或 # 也是注释，键不分大小写
+    // 出错返回 (行号, 原因)
+    fn parse_ini(&mut self, text: &str) -> core::result::Result<(), (usize, String)> {
+        let mut section = String::new();
+        for (no, line) in text.trim_start_matches('\u{feff}').lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with([';', '#']) { continue }
+
+            if let Some(name) = line.strip_prefix('[') {
+                let name = name.strip_suffix(']').ok_or((no + 1, format!("unclosed section '{}'", line)))?;
+                section = name.trim().to_ascii_lowercase();
+                continue;
+            }
+            let (key, value) = line.split_once('=').ok_or((no + 1, format!("expected key = value, got '{}'", line)))?;
+            let key = key.trim().to_ascii_lowercase();
+            self.set(&section, &key, strip_comment(value.trim())).map_err(|msg| (no + 1, msg))?;
+        }
+        Ok(())
+    }
+
+    /// 按 (节, 键) 设一项，命令行也走这里，出错返回原因
+    pub fn set(&mut self, section: &str, key: &str, value: &str) -> core::result::Result<(), String> {
+        let bad = |what: &str| format!("invalid {} '{}'", what, value);
+        match (section, key) {
+            ("video", "source") => self.source = value.to_string(),
+            ("video", "browse") => self.browse = parse_bool(value).ok_or_else(|| bad("switch"))?,
+            ("video", "fps") => self.fps = value.parse().ok().filter(|&f| f > 0).ok_or_else(|| bad("fps"))?,
+            ("video", "loop") => self.loop_mode = LoopMode::from_name(value).ok_or_else(|| bad("loop mode"))?,
+            ("video", "renderer") => self.renderer = Renderer::from_name(value).ok_or_else(|| bad("renderer"))?,
+            ("video", "cores") => self.cores = value.parse().map_err(|_| bad("core count"))?,
+
+            ("display", "mode") => self.mode = parse_mode(value).ok_or_else(|| bad("mode"))?,
+            ("display", "scale") => self.scale = ScaleMode::from_name(value).ok_or_else(|| bad("scale mode"))?,
+            ("display", "filter") => self.filter = ScaleFilter::from_name(value).ok_or_else(|| bad("scale filter"))?,
+            ("display", "border") => self.border = parse_color(value).ok_or_else(|| bad("color"))?,
+            ("display", "rotation") => self.rotation = Rotation::from_name(value).ok_or_else(|| bad("rotation"))?,
+            ("display", "hud") => self.hud = parse_bool(value).ok_or_else(|| bad("switch"))?,
+            ("display", "font") => self.font = (!value.is_empty()).then(|| value.to_string()),
+            ("display", "font_scale") => self.font_scale = parse_scale(value).ok_or_else(|| bad("font scale"))?,
+
+            ("filters", "enabled") => self.filters_enabled = parse_bool(value).ok_or_else(|| bad("switch"))?,
+            ("filters", "chain") => {
+                self.filters = value.split(',')
+                    .filter(|f| !f.trim().is_empty())
+                    .map(|f| ColorFilter::from_name(f).ok_or_else(|| format!("invalid filter '{}'", f.trim())))
+                    .collect::<core::result::Result<_, _>>()?
+            }
+
+            ("boot", "chain") => self.chain = (!value.is_empty()).then(|| value.to_string()),
+            ("boot", "timeout") => self.splash_timeout = value.parse().map_err(|_| bad("timeout"))?,
+
+            ("log", "console") => self.log_console = value.parse().map_err(|_| bad("log level"))?,
+            ("log", "serial") => self.log_serial = value.parse().map_err(|_| bad("log level"))?,
+            ("log", "file") => self.log_file = value.parse().map_err(|_| bad("log level"))?,
+
+            ("", _) => return Err(format!("key '{}' outside of any section", key)),
+            _ => return Err(format!("unknown key '{}' in [{}]", key, section)),
+        }
+        Ok(())
+    }
+}
+
+// auto 或者 1920x1080
+fn parse_mode(value: &str) -> Option<Option<(usize, usize)>> {
+    if value.eq_ignore_ascii_case("auto") { return Some(None) }
+    let (w, h) = value.split_once(['x', 'X'])?;
+    Some(Some((w.trim().parse().ok()?, h.trim().parse().ok()?)))
+}
+
+// 去掉值后面的行内注释，要求 ; 或 # 前面有空白，border = #000000 这种不算
+fn strip_comment(value: &str) -> &str {
+    let end = value.char_indices()
+        .zip(value.chars().skip(1))
+        .find(|&((_, c), next)| c.is_whitespace() && matches!(next, ';' | '#'))
+        .map_or(value.len(), |((i, _), _)| i);
+    value[..end].trim_end()
+}
+
+// auto 或者 1 到 8
+fn parse_scale(value: &str) -> Option<usize> {
+    if value.eq_ignore_ascii_case("auto") { return Some(0) }
+    value.parse().ok().filter(|n| (1..=8).contains(n))
+}
+
+// #RRGGBB 或 0xRRGGBB
+fn parse_color(value: &str) -> Option<u32> {
+    let hex = value.strip_prefix('#').or_else(|| value.strip_prefix("0x"))?;
+    if hex.len() != 6 { return None }
+    u32::from_str_radix(hex, 16).ok()
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match value.to_ascii_lowercase().as_str() {
+        "on" | "true" | "yes" | "1" => Some(true),
+        "off" | "false" | "no" | "0" => Some(false),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = example!();
+
+    #[test]
+    fn inline_comments_are_stripped() {
+        let mut config = Config::default();
+        config.parse_ini("[video]\nsource = 1080p\\video.qois   ; 视频\nloop = forever ; once / 3\n[display]\nborder = #102030 # 补边\n").unwrap();
+        assert_eq!(config.source, "1080p\\video.qois");
+        assert!(matches!(config.loop_mode, LoopMode::Forever));
+        assert_eq!(config.border, 0x102030);
+    }
+
+    #[test]
+    fn documented_example_parses() {
+        Config::default().parse_ini(EXAMPLE).unwrap();
+    }
+}
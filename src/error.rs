@@ -32,8 +32,22 @@ impl From<qoi::Error> for NyaStatus {
     fn from(e: qoi::Error) -> Self { NyaStatus::Qoi(e) }
 }
 
+impl<Data: Debug> NyaStatus<Data> {
+    /// 返回给固件的状态码，不是 UEFI 来源的错误统一算 ABORTED
+    pub fn status(&self) -> uefi::Status {
+        match self {
+            NyaStatus::Uefi(e) => e.status(),
+            NyaStatus::NotRegularFile => uefi::Status::INVALID_PARAMETER,
+            NyaStatus::Qoi(_) => uefi::Status::VOLUME_CORRUPTED,
+            _ => uefi::Status::ABORTED,
+        }
+    }
+}
+
 // 垫底的错误处理，至少是安全的输出内容（大雾
-pub fn handle_fatal(err: NyaStatus, screen: &mut Screen) -> ! {
+// 停留一段时间让人看清，然后把状态码交回去，由 main 返回给固件
+pub fn handle_fatal(err: NyaStatus, screen: &mut Screen) -> uefi::Status {
+    let status = err.status();
     let _ = screen.clear();
 
     // 可变参数宏简化
@@ -41,19 +55,20 @@ pub fn handle_fatal(err: NyaStatus, screen: &mut Screen) -> ! {
         ($($arg:tt)*) => { screen.draw_str(&alloc::format!($($arg)*)) }
     }
 
-    println!("KERNEL PANIC!");
+    // 控制台认 ANSI 颜色
+    println!("\x1b[1;41m KERNEL PANIC! \x1b[0m");
 
     match err {
-        NyaStatus::Qoi(err) => println!("QOI error: {}", err),
+        NyaStatus::Qoi(err) => println!("\x1b[93mQOI error:\x1b[0m {}", err),
         NyaStatus::_Debug(err) => screen.draw_str(&err),
-        _ => println!("FATAL ERROR: {:?}", err),
+        _ => println!("\x1b[93mFATAL ERROR:\x1b[0m {:?}", err),
     }
 
-    println!("System will stall for 1 minute before returning.");
+    println!("\x1b[90mSystem will stall for 1 minute before returning.\x1b[0m");
 
-    stall(Duration::from_mins(2));
+    stall(Duration::from_mins(1));
 
-    panic!("Unrecoverable error occurred.");
+    status
 }
 
 // TODO: 高耦合,之后分离
@@ -1,22 +1,92 @@
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::{String, ToString};
 use alloc::vec;
 use alloc::vec::Vec;
-use uefi::boot::{get_image_file_system, image_handle};
-use uefi::{CStr16, Status};
-use uefi::proto::media::file::{Directory, File, FileAttribute, FileInfo, FileMode, RegularFile};
+use uefi::boot::{self, get_image_file_system, image_handle, OpenProtocolAttributes, OpenProtocolParams};
+use uefi::{CStr16, CString16, Status};
+use uefi::proto::device_path::DevicePath;
+use uefi::proto::device_path::text::{AllowShortcuts, DisplayOnly};
+use uefi::proto::media::file::{Directory, File, FileAttribute, FileInfo, FileMode, FileSystemInfo, FileType, RegularFile};
+use uefi::proto::media::fs::SimpleFileSystem;
 use crate::error::{NyaStatus, Result};
+use crate::net::{is_tftp, Tftp};
 
 
+/// 文件系统,路径可以带 fsN: 或者 卷标: 前缀指定卷,不带的在本程序所在的卷上找
 pub struct Fs {
+    /// 本程序所在的卷
     pub root_dir: Directory,
+    /// 所有能打开的卷,按固件给的句柄顺序,fsN: 里的 N 就是下标
+    /// 和 Shell 的编号不一定一样
+    pub volumes: Vec<Volume>,
+}
+
+/// 一个挂载的卷
+pub struct Volume {
+    pub root: Directory,
+    /// 卷标,没有就是空串
+    pub label: String,
+    /// 设备路径的文字形式,固件不支持转换时为空串
+    pub device: String,
+    /// 卷大小,字节
+    pub size: u64,
+}
+
+/// 目录里的一项
+#[derive(Debug, Clone)]
+pub struct DirEntry {
+    pub name: String,
+    pub is_dir: bool,
+    pub size: u64,
 }
 
 impl Fs {
     pub fn new() -> Result<Self> {
         Ok(Self {
-            root_dir: get_image_file_system(image_handle())?.open_volume()?
+            root_dir: get_image_file_system(image_handle())?.open_volume()?,
+            volumes: Self::mount_all(),
         })
     }
 
+    // 打不开的卷直接跳过,不影响本卷
+    fn mount_all() -> Vec<Volume> {
+        let Ok(handles) = boot::find_handles::<SimpleFileSystem>() else { return Vec::new() };
+        handles.into_iter().filter_map(|handle| {
+            // 只借用不独占,独占会把文件系统驱动断开
+            let params = OpenProtocolParams { handle, agent: image_handle(), controller: None };
+            let mut sfs = unsafe { boot::open_protocol::<SimpleFileSystem>(params, OpenProtocolAttributes::GetProtocol) }.ok()?;
+            let mut root = sfs.open_volume().ok()?;
+            let (label, size) = match root.get_boxed_info::<FileSystemInfo>() {
+                Ok(info) => (info.volume_label().to_string(), info.volume_size()),
+                Err(_) => (String::new(), 0),
+            };
+            let device = unsafe { boot::open_protocol::<DevicePath>(params, OpenProtocolAttributes::GetProtocol) }.ok()
+                .and_then(|path| path.to_string(DisplayOnly(true), AllowShortcuts(true)).ok())
+                .map(|text| text.to_string())
+                .unwrap_or_default();
+            Some(Volume { root, label, device, size })
+        }).collect()
+    }
+
+    /// fsN 或者卷标(不分大小写)对应的卷下标
+    pub fn volume_index(&self, name: &str) -> Option<usize> {
+        let by_number = name.get(..2)
+            .filter(|p| p.eq_ignore_ascii_case("fs"))
+            .and_then(|_| name[2..].parse::<usize>().ok())
+            .filter(|&i| i < self.volumes.len());
+        by_number.or_else(|| self.volumes.iter().position(|v| !v.label.is_empty() && v.label.eq_ignore_ascii_case(name)))
+    }
+
+    // 拆出卷前缀,返回卷的根目录和卷内路径,卷内路径相对根目录,空串就是根目录
+    fn resolve(&mut self, path: &CStr16) -> Result<(&mut Directory, CString16)> {
+        let text = path.to_string();
+        let Some((volume, rest)) = text.split_once(':') else { return Ok((&mut self.root_dir, path.into())) };
+        let index = self.volume_index(volume)
+            .ok_or_else(|| NyaStatus::_Debug(format!("no volume named '{}:'", volume)))?;
+        Ok((&mut self.volumes[index].root, to_path(rest.trim_start_matches(['\\', '/']))?))
+    }
+
     // 没有方法重载 没有可选参数 没有默认形参值！草了
     // <- 给古老的我:对于编译时确定的值可以用宏($($arg:tt)*)来实现可变参数
     // <- 给过去的我:骗你的，format_args是硬编码
@@ -32,41 +102,77 @@ impl Fs {
 
     #[inline]
     pub fn open_file_attr(&mut self, path: &CStr16, mode: FileMode, attr: FileAttribute) -> Result<RegularFile> {
-        self.root_dir
-            .open(path, mode, attr)?
+        let (root, path) = self.resolve(path)?;
+        root
+            .open(&path, mode, attr)?
             .into_regular_file()
             .ok_or(NyaStatus::NotRegularFile)
     }
 
-    // 一次性读取全部内容，慎用
-    pub fn read_file(&mut self, path: &CStr16) -> Result<Vec<u8>> {
-        let mut file = self.open_file(path)?;
+    /// 路径是不是目录，不存在直接报错
+    pub fn is_dir(&mut self, path: &CStr16) -> Result<bool> {
+        let (root, path) = self.resolve(path)?;
+        // 卷根目录本身
+        if path.is_empty() { return Ok(true) }
+        let file = root.open(&path, FileMode::Read, FileAttribute::empty())?;
+        Ok(matches!(file.into_type()?, FileType::Dir(_)))
+    }
 
-        // 获取文件信息
-        let mut info_buf = vec![0u8; 128];
-        let info = loop {
-            match file.get_info::<FileInfo>(&mut info_buf) {
-                Ok(info) => break info,
-                Err(e) if e.status() == Status::BUFFER_TOO_SMALL =>
-                    if let Some(size) = *e.data() {
-                        info_buf.resize(size, 0)
-                    },
-                // 其他状态的Option均为None,这里拉平
-                Err(e) => Err(e.status())?,
-            }
+    /// 列出目录下的普通文件名（不含子目录），顺序由固件决定
+    pub fn read_dir(&mut self, path: &CStr16) -> Result<Vec<String>> {
+        Ok(self.list(path)?.into_iter().filter(|e| !e.is_dir).map(|e| e.name).collect())
+    }
+
+    /// 列出目录下所有项，不含 . 和 ..，空路径为卷根目录
+    pub fn list(&mut self, path: &CStr16) -> Result<Vec<DirEntry>> {
+        let (root, path) = self.resolve(path)?;
+        let mut opened;
+        let dir = if path.is_empty() {
+            // 根目录一直开着，从头读
+            root.reset_entry_readout()?;
+            root
+        } else {
+            opened = match root.open(&path, FileMode::Read, FileAttribute::empty())?.into_type()? {
+                FileType::Dir(dir) => dir,
+                FileType::Regular(_) => Err(Status::INVALID_PARAMETER)?,
+            };
+            &mut opened
         };
 
-        // 获取完整内容
-        let mut file_content = vec![0u8; info.file_size() as usize];
-        file.read(&mut file_content)?;
+        let mut info_buf = vec![0u8; 256];
+        let mut entries = Vec::new();
+        loop {
+            let info = match dir.read_entry(&mut info_buf) {
+                Ok(Some(info)) => info,
+                Ok(None) => break,
+                Err(e) if e.status() == Status::BUFFER_TOO_SMALL => {
+                    if let Some(size) = *e.data() { info_buf.resize(size, 0) }
+                    continue;
+                }
+                Err(e) => Err(e.status())?,
+            };
+            let name = info.file_name().to_string();
+            if name == "." || name == ".." { continue }
+            entries.push(DirEntry { name, is_dir: info.is_directory(), size: info.file_size() });
+        }
+        Ok(entries)
+    }
 
-        Ok(file_content)
+    // 一次性读取全部内容，慎用
+    pub fn read_file(&mut self, path: &CStr16) -> Result<Vec<u8>> {
+        self.open_file(path)?.read_all()
     }
 
-    pub fn read_frame_next(&mut self, file: &mut RegularFile, buf: &mut Vec<u8>) -> Result<bool> {
+    /// 打开顺序读的数据源，tftp: 开头的走网络，其余是卷上的文件
+    pub fn open_reader(&mut self, path: &str) -> Result<Box<dyn Reader>> {
+        if is_tftp(path) { return Ok(Box::new(Tftp::open(path)?)) }
+        Ok(Box::new(self.open_file(&to_path(path)?)?))
+    }
+
+    pub fn read_frame_next(&mut self, file: &mut dyn Reader, buf: &mut Vec<u8>) -> Result<bool> {
         // 读头
         let mut qoi_size = [0u8; 4];
-        if file.read(&mut qoi_size)? < 4 {
+        if file.read_chunk(&mut qoi_size)? < 4 {
             return Ok(false);
         }
         let qoi_size = u32::from_le_bytes(qoi_size) as usize;
@@ -76,10 +182,70 @@ impl Fs {
             buf.resize(qoi_size, 0);
         }
 
-        // 写入一帧
-        file.read(&mut buf[..qoi_size])?;
+        // 写入一帧,头说的长度读不满就是文件被截断了
+        if file.read_chunk(&mut buf[..qoi_size])? != qoi_size {
+            Err(Status::VOLUME_CORRUPTED)?
+        }
 
         Ok(true)
     }
 }
 
+/// 顺序读的数据源，卷上的文件和 TFTP 都实现它，解码和流式播放只认这个
+pub trait Reader {
+    /// 读满 buf 或者读到结尾，返回读到的字节数，0 为结尾
+    fn read_chunk(&mut self, buf: &mut [u8]) -> Result<usize>;
+    /// 回到开头
+    fn rewind(&mut self) -> Result;
+    /// 总长度，字节
+    fn size(&mut self) -> Result<u64>;
+
+    /// 从当前位置读到结尾
+    fn read_all(&mut self) -> Result<Vec<u8>> {
+        let mut data = vec![0u8; self.size()? as usize];
+        let mut filled = 0;
+        while filled < data.len() {
+            let read = self.read_chunk(&mut data[filled..])?;
+            if read == 0 { break }
+            filled += read;
+        }
+        data.truncate(filled);
+        Ok(data)
+    }
+}
+
+impl Reader for RegularFile {
+    #[inline]
+    fn read_chunk(&mut self, buf: &mut [u8]) -> Result<usize> {
+        Ok(self.read(buf)?)
+    }
+
+    #[inline]
+    fn rewind(&mut self) -> Result {
+        Ok(self.set_position(0)?)
+    }
+
+    fn size(&mut self) -> Result<u64> {
+        let mut info_buf = vec![0u8; 128];
+        loop {
+            match self.get_info::<FileInfo>(&mut info_buf) {
+                Ok(info) => return Ok(info.file_size()),
+                // 没给出需要的大小就没法重试,当普通错误返回
+                Err(e) if e.status() == Status::BUFFER_TOO_SMALL && let Some(size) = *e.data() => info_buf.resize(size, 0),
+                // 其他状态的Option均为None,这里拉平
+                Err(e) => Err(e.status())?,
+            }
+        }
+    }
+}
+
+/// &str 路径转 UEFI 的 UCS-2 字符串，顺便把 / 换成 \
+pub fn to_path(path: &str) -> Result<CString16> {
+    let path: String = path.chars().map(|c| if c == '/' { '\\' } else { c }).collect();
+    CString16::try_from(path.as_str()).map_err(|_| NyaStatus::FromStrWithBufError)
+}
+
+/// 目录后面接上名字,目录为空时就是名字本身
+pub fn join(dir: &str, name: &str) -> String {
+    if dir.is_empty() { name.to_string() } else { format!("{}\\{}", dir.trim_end_matches(['\\', '/']), name) }
+}
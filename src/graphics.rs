@@ -1,25 +1,156 @@
+use alloc::vec;
+use alloc::vec::Vec;
 use uefi::boot::{get_handle_for_protocol, open_protocol_exclusive, ScopedProtocol};
 use uefi::proto::console::gop::{BltOp, BltPixel, BltRegion, GraphicsOutput, Mode};
 use uefi::proto::pi::mp::MpServices;
 use crate::error::Result;
-use crate::video::ascii_font::FONT_8X16;
+use crate::graphics::console::Console;
+use crate::graphics::font::draw_glyph;
+use crate::graphics::filter::FilterChain;
+use crate::graphics::mode::{choose_mode, current_mode};
+use crate::graphics::overlay::Compositor;
+use crate::graphics::pixel::PixelLayout;
+use crate::graphics::rotate::Rotation;
+use crate::graphics::scale::Scaler;
 use crate::video::decoder::VideoMemoryRaw;
 
+pub mod pixel;
+pub mod mode;
+pub mod scale;
+pub mod rotate;
+pub mod filter;
+pub mod dither;
+pub mod edid;
+pub mod overlay;
+pub mod console;
+pub mod font;
+
 pub struct Screen {
     gop: ScopedProtocol<GraphicsOutput>,
-    stdout: usize,
+    // 错误信息之类的文字输出
+    console: Console,
+    // 启动时固件设置的模式，退出时恢复
+    original_mode: Option<Mode>,
+    // 面板安装方向，文字和单核输出都按它旋转
+    rotation: Rotation,
 }
 
 impl Screen {
     pub fn new() -> Result<Self> {
         let handle = get_handle_for_protocol::<GraphicsOutput>()?;
-        let mut gop = open_protocol_exclusive::<GraphicsOutput>(handle)?;
-        Ok(Self { gop, stdout: 0 })
+        let gop = open_protocol_exclusive::<GraphicsOutput>(handle)?;
+        let original_mode = current_mode(&gop);
+        Ok(Self { gop, console: Console::new(), original_mode, rotation: Rotation::Deg0 })
+    }
+
+    pub fn set_rotation(&mut self, rotation: Rotation) {
+        self.rotation = rotation;
+        self.console.home();
+    }
+
+    #[inline]
+    pub fn rotation(&self) -> Rotation { self.rotation }
+
+    /// 当前模式的刷新率(毫赫兹),EDID 首选时序和当前分辨率对得上才算数
+    pub fn refresh_rate(&self) -> Option<u32> {
+        let handle = get_handle_for_protocol::<GraphicsOutput>().ok()?;
+        let (resolution, refresh) = edid::read_preferred_timing(handle)?;
+        (resolution == self.gop.current_mode_info().resolution()).then_some(refresh)
+    }
+
+    /// 旋转后用户看到的分辨率
+    #[inline]
+    pub fn resolution(&self) -> (usize, usize) {
+        self.rotation.logical_size(self.gop.current_mode_info().resolution())
+    }
+
+    /// 按视频分辨率切换显示模式，wanted 为配置强制指定的分辨率
+    /// 切换会清屏，并使之前拿到的 frame_buffer 指针失效
+    pub fn select_mode(&mut self, video: (usize, usize), wanted: Option<(usize, usize)>) -> Result {
+        // 竖装面板上，物理模式要和转过来的视频比
+        let video = self.rotation.logical_size(video);
+        let Some(mode) = choose_mode(&self.gop, video, wanted) else { return Ok(()) };
+        if *mode.info() != self.gop.current_mode_info() {
+            self.gop.set_mode(&mode)?;
+            self.console.home();
+        }
+        Ok(())
+    }
+
+    /// 恢复到固件启动时的模式
+    pub fn restore_mode(&mut self) -> Result {
+        if let Some(mode) = self.original_mode && *mode.info() != self.gop.current_mode_info() {
+            self.gop.set_mode(&mode)?;
+        }
+        Ok(())
     }
 
     pub fn get_gop(&mut self) -> &mut ScopedProtocol<GraphicsOutput> { &mut self.gop }
 
+    /// 当前模式的显存像素布局
+    #[inline]
+    pub fn layout(&self) -> PixelLayout { PixelLayout::from_mode(&self.gop.current_mode_info()) }
+
+    /// 按当前像素格式把一帧 BGRA 画到 (0,0),超出屏幕的部分裁掉
+    /// BltOnly 模式没有线性显存,退回 BufferToVideo
+    pub fn present(&mut self, pixels: &[BltPixel], width: usize, height: usize) -> Result {
+        if self.rotation != Rotation::Deg0 {
+            return self.present_rotated(pixels, width, height);
+        }
+
+        let layout = self.layout();
+        if !layout.has_framebuffer() {
+            return Ok(self.gop.blt(BltOp::BufferToVideo {
+                buffer: pixels,
+                src: BltRegion::Full,
+                dest: (0, 0),
+                dims: (width, height),
+            })?);
+        }
+
+        let mode_info = self.gop.current_mode_info();
+        let (scr_width, scr_height) = mode_info.resolution();
+        let stride_bytes = mode_info.stride() * layout.bytes_per_pixel();
+        let (w, h) = (width.min(scr_width), height.min(scr_height));
+
+        let mut fb = self.gop.frame_buffer();
+        let dest_ptr = fb.as_mut_ptr();
+        for y in 0..h {
+            let row = &pixels[y * width..y * width + w];
+            unsafe { layout.write_row(row, dest_ptr.add(y * stride_bytes), 0, y) }
+        }
+        Ok(())
+    }
+
+    // 只有单核路径会走到这里
+    fn present_rotated(&mut self, pixels: &[BltPixel], width: usize, height: usize) -> Result {
+        self.blit(pixels, (0, 0, width, height))
+    }
+
+    /// 把一块像素画到逻辑坐标 (x, y, w, h)，pixels 每行 w 个，超出屏幕的部分裁掉
+    pub fn blit(&mut self, pixels: &[BltPixel], rect: (usize, usize, usize, usize)) -> Result {
+        blit_region(&mut self.gop, self.rotation, pixels, (0, rect.2), rect)
+    }
+
+    /// 在逻辑坐标 (x, y) 画一行字，高一个字符格，宽字符占两格，整条 width 宽先用 bg 铺满，一次 blt 画完
+    pub fn draw_text(&mut self, (x, y): (usize, usize), width: usize, text: &str, fg: BltPixel, bg: BltPixel) -> Result {
+        let (cw, ch) = font::cell();
+        let mut strip = vec![bg; width * ch];
+        let mut x0 = 0;
+        for c in text.chars() {
+            let w = font::width(c) * cw;
+            if x0 + w > width { break }
+            draw_glyph(&mut strip, width, x0, c, fg);
+            x0 += w;
+        }
+        self.blit(&strip, (x, y, width, ch))
+    }
+
     pub fn draw_image(&mut self, width: u32, height: u32, pixels: &[BltPixel]) -> Result {
+        if self.rotation != Rotation::Deg0 {
+            return self.present_rotated(pixels, width as usize, height as usize);
+        }
+
         // 我不知道为什么封装成这样了，但是它能工作！
         // 默认blt输出uefi::result::Result，这里?拆包然后Ok封装为crate::error::Result
         // ?外的Ok并不会影响错误抛出
@@ -31,115 +162,129 @@ impl Screen {
         })?)
     }
 
+    /// 缩放 + 滤镜后整屏输出，单核路径用
+    /// canvas 为复用的临时缓冲：有显存时只用一行，BltOnly 时扩到整屏
+    /// overlay 为 (叠加层, 轮次)，在滤镜之后合成
+    pub fn present_scaled(&mut self, frame: &[BltPixel], scaler: &Scaler, filters: &FilterChain, overlay: Option<(&Compositor, usize)>, canvas: &mut Vec<BltPixel>) -> Result {
+        let layout = self.layout();
+        let mode_info = self.gop.current_mode_info();
+        let (scr_width, scr_height) = mode_info.resolution();
+        let covers = |y: usize| overlay.is_some_and(|(c, round)| c.covers(round, y));
+        let blend = |row: &mut [BltPixel], y: usize| {
+            if let Some((c, round)) = overlay { c.blend_row(round, y, row) }
+        };
+
+        if !layout.has_framebuffer() {
+            canvas.resize(scr_width * scr_height, BltPixel::new(0, 0, 0));
+            for (y, row) in canvas.chunks_exact_mut(scr_width).enumerate() {
+                scaler.render_row(frame, y, row);
+                filters.apply_slice(row);
+                blend(row, y);
+            }
+            // 缩放表已经处理过旋转，这里是物理坐标
+            return Ok(self.gop.blt(BltOp::BufferToVideo {
+                buffer: canvas,
+                src: BltRegion::Full,
+                dest: (0, 0),
+                dims: (scr_width, scr_height),
+            })?);
+        }
+
+        canvas.resize(scr_width, BltPixel::new(0, 0, 0));
+        let stride_bytes = mode_info.stride() * layout.bytes_per_pixel();
+        let mut fb = self.gop.frame_buffer();
+        let dest_ptr = fb.as_mut_ptr();
+        for y in 0..scr_height {
+            let dst = unsafe { dest_ptr.add(y * stride_bytes) };
+            if covers(y) {
+                scaler.render_row(frame, y, canvas);
+                filters.apply_slice(canvas);
+                blend(canvas, y);
+                unsafe { layout.write_row(canvas, dst, 0, y) }
+                continue;
+            }
+            let row = scaler.row(frame, y, canvas);
+            if filters.active() {
+                unsafe { layout.write_row_map(row, dst, 0, y, |p| filters.apply(p)) }
+            } else {
+                unsafe { layout.write_row(row, dst, 0, y) }
+            }
+        }
+        Ok(())
+    }
+
     pub fn clear(&mut self) -> Result {
         let info = self.gop.current_mode_info();
         let (width, height) = info.resolution();
 
         // 使用 VideoFill 操作，这比传输像素数组快得多
-        Ok(self.gop.blt(BltOp::VideoFill {
+        self.gop.blt(BltOp::VideoFill {
             // 黑色像素：Red=0, Green=0, Blue=0
             color: BltPixel::new(0, 0, 0),
             dest: (0, 0),
             dims: (width, height),
-        })?)
+        })?;
+        self.console.home();
+        Ok(())
     }
 
-    // 糟糕的 ASCII 输出实现,一个字符的一个笔画开始渲染
-    pub fn draw_str(&mut self, text: &str) {
-        let mut x = 0;
-        // 获取当前屏幕的宽度，用于自动换行
-        let (width, height) = self.gop.current_mode_info().resolution();
-
-        let fg = BltPixel::new(255, 255, 255);
-        let bg = BltPixel::new(0, 0, 0);
-
-        // 防止超出高度
-        if self.stdout + 20 >= height { self.stdout = 0 }
-
-        for c in text.chars() {
-            // --- 1. 处理换行逻辑 ---
-
-            // 显式换行符处理
-            if c == '\n' {
-                x = 0;
-                self.stdout += 18; // 换行高度（点阵16像素 + 2像素间距）
-                if self.stdout >= height { self.stdout = 0 }
-                continue;
-            }
-
-            // 超出屏幕宽度自动换行 (8 是字符宽度)
-            if x + 8 > width {
-                x = 0;
-                self.stdout += 18;
-                if self.stdout >= height { self.stdout = 0 }
-            }
-
-            // --- 2. 绘制字符 ---
-
-            let index = (c as usize) & 0x7F;
-            let glyph = &FONT_8X16[index];
-
-            for row in 0..16 {
-                let row_bits = glyph[row];
-                for col in 0..8 {
-                    let is_fg = (row_bits >> (7 - col)) & 1 == 1;
-                    let color = if is_fg { fg } else { bg };
-
-                    // 绘制像素
-                    let _ = self.gop.blt(BltOp::VideoFill {
-                        color,
-                        dest: (x + col, self.stdout + row),
-                        dims: (1, 1),
-                    });
-                }
-            }
-
-            // 字符绘制完后，坐标右移
-            x += 8;
-        }
+    /// 在文本控制台上输出,接着上次的光标位置
+    pub fn print(&mut self, text: &str) -> Result {
+        self.console.write(&mut self.gop, self.rotation, text)
+    }
 
-        // 整个字符串画完后，最后额外换一行，防止下一次打印重叠
-        self.stdout += 18;
+    /// 输出一行,错误画面用,画不出来也没有别的办法,忽略错误
+    pub fn draw_str(&mut self, text: &str) {
+        let _ = self.print(text);
+        let _ = self.print("\n");
     }
 
-    pub fn draw_all_mem_raw_zero_copy(&mut self, video: &mut VideoMemoryRaw, width: usize, height: usize) {
+    /// 返回 Ok(false) 表示按循环策略播完了，blt 失败原样返回
+    pub fn draw_all_mem_raw_zero_copy(&mut self, video: &mut VideoMemoryRaw, width: usize, height: usize) -> Result<bool> {
         // 获取下一帧的原始像素引用
-        if let Some(pixel_slice) = video.next_frame() {
-            // 直接绘制到屏幕
-            // 假设你的屏幕分辨率和视频一致，从 (0,0) 开始画
-            self.gop.blt(
-                BltOp::BufferToVideo {
-                    buffer: pixel_slice,
-                    src: BltRegion::Full,
-                    dest: (0, 0),
-                    dims: (width, height),
-                }
-            ).ok();
-        } else {
-            video.rewind();
+        let Some(pixel_slice) = video.next_frame() else { return Ok(false) };
+        if self.rotation != Rotation::Deg0 {
+            self.present_rotated(pixel_slice, width, height)?;
+            return Ok(true);
         }
+
+        // 直接绘制到屏幕
+        // 假设你的屏幕分辨率和视频一致，从 (0,0) 开始画
+        self.gop.blt(
+            BltOp::BufferToVideo {
+                buffer: pixel_slice,
+                src: BltRegion::Full,
+                dest: (0, 0),
+                dims: (width, height),
+            }
+        )?;
+        Ok(true)
     }
 
 
-    /// 高性能显存直接写入
+    /// 高性能显存直接写入，返回 false 表示按循环策略播完了
     pub fn draw_fast_direct_copy(
         &mut self,
         video: &mut VideoMemoryRaw,
         width: usize,
         height: usize
-    ) {
+    ) -> bool {
         // 1. 获取下一帧
-        let pixel_slice = match video.next_frame() {
-            Some(slice) => slice,
-            None => {
-                video.rewind();
-                return;
-            }
-        };
+        let Some(pixel_slice) = video.next_frame() else { return false };
 
         // 2. 先提取 ModeInfo（此时 gop 会被借用，但在这一行结束后就会释放）
         let mode_info = self.gop.current_mode_info();
         let stride = mode_info.stride();
+        // 视频比屏幕大时只拷屏幕能装下的部分,帧不完整时只拷完整的行
+        let (scr_w, scr_h) = mode_info.resolution();
+        let w = width.min(scr_w);
+        let h = height.min(scr_h).min(pixel_slice.len() / width.max(1));
+
+        // 非 BGR 格式或者旋转了就没法直接拷,交给 present 转换
+        if self.layout() != PixelLayout::Bgr || self.rotation != Rotation::Deg0 {
+            let _ = self.present(pixel_slice, width, height);
+            return true;
+        }
 
         // 3. 再获取 FrameBuffer（此时 gop 被独占借用）
         let mut fb = self.gop.frame_buffer();
@@ -147,26 +292,28 @@ impl Screen {
 
         // 4. 执行内存拷贝
         unsafe {
-            if stride == width {
+            if stride == width && w == width {
                 // 全局一次性拷贝
                 core::ptr::copy_nonoverlapping(
                     pixel_slice.as_ptr() as *const u8,
                     dest_ptr,
-                    width * height * 4
+                    w * h * 4
                 );
             } else {
-                // 考虑 Stride 的逐行拷贝
+                // 考虑 Stride 的逐行拷贝,源行距是视频宽度
                 let src_ptr = pixel_slice.as_ptr() as *const u8;
-                for y in 0..height {
+                for y in 0..h {
                     let row_src = src_ptr.add(y * width * 4);
                     let row_dest = dest_ptr.add(y * stride * 4);
-                    core::ptr::copy_nonoverlapping(row_src, row_dest, width * 4);
+                    core::ptr::copy_nonoverlapping(row_src, row_dest, w * 4);
                 }
             }
         }
+        true
     }
 
 
+    /// 只适用于 BGR 线性显存,其他格式请用 present
     #[inline]
     pub fn draw_u64_optimized(
         &mut self,
@@ -176,11 +323,8 @@ impl Screen {
         stride: usize,
         is_continuous: bool,
         dest_ptr: *mut u8
-    ) {
-        let pixel_slice = match video.next_frame() {
-            Some(slice) => slice,
-            None => { video.rewind(); return; }
-        };
+    ) -> bool {
+        let Some(pixel_slice) = video.next_frame() else { return false };
 
         let src_ptr = pixel_slice.as_ptr() as *const u8;
 
@@ -200,18 +344,23 @@ impl Screen {
                 }
             }
         }
+        true
     }
 
     pub fn draw_u64_optimized_loop(&mut self, video: &mut VideoMemoryRaw, width: usize, height: usize) {
+        // 格式不对或者旋转了就退回逐行转换的版本
+        if self.layout() != PixelLayout::Bgr || self.rotation != Rotation::Deg0 {
+            while self.draw_fast_direct_copy(video, width, height) {}
+            return;
+        }
+
         let mode_info = self.gop.current_mode_info();
         let stride = mode_info.stride();
         let mut fb = self.gop.frame_buffer();
         let is_continuous = stride == width;
         let dest_ptr = fb.as_mut_ptr();
 
-        loop {
-            self.draw_u64_optimized(video, width, height, stride, is_continuous, dest_ptr);
-        }
+        while self.draw_u64_optimized(video, width, height, stride, is_continuous, dest_ptr) {}
     }
 
     pub fn parallel_video_draw(&mut self, video: &mut VideoMemoryRaw, width: usize, height: usize) -> Result {
@@ -226,6 +375,46 @@ impl Screen {
 
 }
 
+/// 把 pixels 里从第 sx 列开始的一块画到逻辑坐标 (x, y, w, h),pixels 每行 stride 个,超出屏幕的部分裁掉
+/// 旋转时逐点转到临时缓冲再 blt,慢,只适合小块或者不在乎速度的地方
+pub fn blit_region(gop: &mut GraphicsOutput, rotation: Rotation, pixels: &[BltPixel], (sx, stride): (usize, usize), (x, y, width, height): (usize, usize, usize, usize)) -> Result {
+    let phys = gop.current_mode_info().resolution();
+    let (lw, lh) = rotation.logical_size(phys);
+    let (w, h) = (width.min(lw.saturating_sub(x)), height.min(lh.saturating_sub(y)));
+    let (px, py, pw, ph) = rotation.rect_to_physical((x, y, w, h), phys);
+    if pw == 0 || ph == 0 { return Ok(()) }
+
+    if rotation == Rotation::Deg0 {
+        return Ok(gop.blt(BltOp::BufferToVideo {
+            buffer: pixels,
+            src: BltRegion::SubRectangle { coords: (sx, 0), px_stride: stride },
+            dest: (px, py),
+            dims: (pw, ph),
+        })?);
+    }
+
+    let mut rotated = vec![BltPixel::new(0, 0, 0); pw * ph];
+    for yy in 0..h {
+        for xx in 0..w {
+            let (tx, ty) = rotation.to_physical(x + xx, y + yy, phys);
+            rotated[(ty - py) * pw + (tx - px)] = pixels[yy * stride + sx + xx];
+        }
+    }
+
+    Ok(gop.blt(BltOp::BufferToVideo {
+        buffer: &rotated,
+        src: BltRegion::Full,
+        dest: (px, py),
+        dims: (pw, ph),
+    })?)
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        let _ = self.restore_mode();
+    }
+}
+
 /// 极致性能的底层拷贝函数（无 self 依赖）
 #[inline(always)]
 unsafe fn u64_fast_copy(src: *const u8, dst: *mut u8, len: usize) {
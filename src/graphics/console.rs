@@ -0,0 +1,369 @@
+use alloc::vec::Vec;
+use uefi::proto::console::gop::{BltOp, BltPixel, GraphicsOutput};
+use crate::error::Result;
+use crate::graphics::blit_region;
+use crate::graphics::font;
+use crate::graphics::rotate::Rotation;
+
+/// 一个字符格的像素大小:字加上八分之一字高的行距,8x16 的字是 8x18,宽字符占两格
+pub fn cell() -> (usize, usize) {
+    let (w, h) = font::cell();
+    (w, h + h / 8)
+}
+
+// CSI 最多记几个参数,再多的丢掉
+const MAX_PARAMS: usize = 16;
+
+// VGA 的 16 色,ANSI 0-7 普通,8-15 高亮
+const PALETTE: [(u8, u8, u8); 16] = [
+    (0, 0, 0), (170, 0, 0), (0, 170, 0), (170, 85, 0),
+    (0, 0, 170), (170, 0, 170), (0, 170, 170), (170, 170, 170),
+    (85, 85, 85), (255, 85, 85), (85, 255, 85), (255, 255, 85),
+    (85, 85, 255), (255, 85, 255), (85, 255, 255), (255, 255, 255),
+];
+
+const DEFAULT_FG: BltPixel = BltPixel::new(255, 255, 255);
+const DEFAULT_BG: BltPixel = BltPixel::new(0, 0, 0);
+
+// SGR 设的颜色,调色板色要等到画的时候才知道粗体要不要换成高亮
+#[derive(Clone, Copy)]
+enum Ink {
+    Default,
+    Palette(u8),
+    Rgb(BltPixel),
+}
+
+// 转义序列解析到哪了
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum State {
+    Ground,
+    // 刚收到 ESC
+    Escape,
+    // ESC [ 之后,等结束字符
+    Csi,
+}
+
+/// GOP 上的文本控制台,逻辑坐标,按字符格排版
+/// 字先画进一行高的缓冲,换行或者一次输出结束时整段 blt 一次,满屏以后整屏上移一行
+///
+/// 认一部分 ANSI/VT100 转义序列:
+/// - SGR `ESC[...m`:0 复位,1/22 粗体,7/27 反色,30-37/90-97/39 前景,40-47/100-107/49 背景,
+///   38;5;n 和 48;5;n 256 色,38;2;r;g;b 和 48;2;r;g;b 真彩色
+/// - 光标 `ESC[r;cH`(`f` 同义),`A` `B` `C` `D` 上下右左移动,`G` 到第几列
+/// - 清除 `ESC[J` 0 到屏尾 1 到屏头 2 整屏,`ESC[K` 0 到行尾 1 到行首 2 整行
+///
+/// 其余序列吞掉不显示
+pub struct Console {
+    fg: Ink,
+    bg: Ink,
+    bold: bool,
+    reverse: bool,
+    // 光标所在的字符格
+    col: usize,
+    row: usize,
+    // 当前行的像素,逻辑宽度 x 字符格高
+    line: Vec<BltPixel>,
+    // 当前行从这一列到光标还没画到屏幕上
+    dirty: Option<usize>,
+    state: State,
+    params: [u16; MAX_PARAMS],
+    // 正在写的是 params[count]
+    count: usize,
+    // ESC[? 这种私有序列,不认
+    private: bool,
+}
+
+// 一次 write 里不变的东西
+struct Surface<'a> {
+    gop: &'a mut GraphicsOutput,
+    rotation: Rotation,
+    // 逻辑分辨率
+    size: (usize, usize),
+    // 字符格大小,一次 write 里不变
+    cell: (usize, usize),
+    cols: usize,
+    rows: usize,
+}
+
+impl Surface<'_> {
+    // 用 color 填满逻辑矩形,超出屏幕的部分裁掉
+    fn fill(&mut self, (x, y, w, h): (usize, usize, usize, usize), color: BltPixel) -> Result {
+        let (w, h) = (w.min(self.size.0.saturating_sub(x)), h.min(self.size.1.saturating_sub(y)));
+        let phys = self.gop.current_mode_info().resolution();
+        let (px, py, pw, ph) = self.rotation.rect_to_physical((x, y, w, h), phys);
+        if pw == 0 || ph == 0 { return Ok(()) }
+        Ok(self.gop.blt(BltOp::VideoFill { color, dest: (px, py), dims: (pw, ph) })?)
+    }
+}
+
+impl Console {
+    pub const fn new() -> Self {
+        Self {
+            fg: Ink::Default,
+            bg: Ink::Default,
+            bold: false,
+            reverse: false,
+            col: 0,
+            row: 0,
+            line: Vec::new(),
+            dirty: None,
+            state: State::Ground,
+            params: [0; MAX_PARAMS],
+            count: 0,
+            private: false,
+        }
+    }
+
+    /// 光标回到左上角,屏幕内容不动,清屏以后或者换了模式时调用
+    pub fn home(&mut self) {
+        self.col = 0;
+        self.row = 0;
+        self.dirty = None;
+    }
+
+    /// 输出一段文字,\n 换行,\r 回行首,行尾自动折行,转义序列见上
+    pub fn write(&mut self, gop: &mut GraphicsOutput, rotation: Rotation, text: &str) -> Result {
+        let (width, height) = rotation.logical_size(gop.current_mode_info().resolution());
+        let (cw, ch) = cell();
+        let (cols, rows) = ((width / cw).max(1), (height / ch).max(1));
+        let mut surface = Surface { gop, rotation, size: (width, height), cell: (cw, ch), cols, rows };
+        if self.line.len() != width * ch {
+            // 模式或者字体变了,旧的行缓冲作废
+            self.line.resize(width * ch, DEFAULT_BG);
+            self.dirty = None;
+        }
+        self.col = self.col.min(cols - 1);
+        self.row = self.row.min(rows - 1);
+
+        for c in text.chars() {
+            match self.state {
+                State::Ground => match c {
+                    '\x1b' => self.state = State::Escape,
+                    '\n' => self.newline(&mut surface)?,
+                    '\r' => self.move_to(&mut surface, 0, self.row)?,
+                    '\x08' => self.move_to(&mut surface, self.col.saturating_sub(1), self.row)?,
+                    '\t' => for _ in 0..8 - self.col % 8 { self.put(&mut surface, ' ')? },
+                    c => self.put(&mut surface, c)?,
+                },
+                State::Escape => {
+                    // 只认 CSI,其他的 ESC x 连同 x 一起丢掉
+                    self.state = if c == '[' { State::Csi } else { State::Ground };
+                    self.params = [0; MAX_PARAMS];
+                    self.count = 0;
+                    self.private = false;
+                }
+                State::Csi => self.csi(&mut surface, c)?,
+            }
+        }
+        self.flush(&mut surface)
+    }
+
+    // 当前的前景、背景色,粗体把调色板前 8 色换成高亮的
+    fn colors(&self) -> (BltPixel, BltPixel) {
+        let resolve = |ink: Ink, default: BltPixel, bright: bool| match ink {
+            Ink::Default => default,
+            Ink::Palette(n) if bright && n < 8 => palette(n + 8),
+            Ink::Palette(n) => palette(n),
+            Ink::Rgb(color) => color,
+        };
+        let (fg, bg) = (resolve(self.fg, DEFAULT_FG, self.bold), resolve(self.bg, DEFAULT_BG, false));
+        if self.reverse { (bg, fg) } else { (fg, bg) }
+    }
+
+    // 画进行缓冲,写满一行自动换行,宽字符放不下时整个挪到下一行
+    fn put(&mut self, surface: &mut Surface, c: char) -> Result {
+        let w = font::width(c).min(surface.cols);
+        if self.col + w > surface.cols { self.newline(surface)? }
+        let (fg, bg) = self.colors();
+        let (cw, ch) = surface.cell;
+        let stride = self.line.len() / ch;
+        let x = self.col * cw;
+        for row in self.line.chunks_exact_mut(stride) {
+            row[x..x + w * cw].fill(bg);
+        }
+        // 只有一列时宽字符放不下,超出那一格的笔画裁掉
+        let span = w * cw;
+        let line = &mut self.line;
+        font::glyph(c, |dx, dy| if dx < span { line[dy * stride + x + dx] = fg });
+        self.dirty.get_or_insert(self.col);
+        self.col += w;
+        Ok(())
+    }
+
+    fn newline(&mut self, surface: &mut Surface) -> Result {
+        self.flush(surface)?;
+        self.col = 0;
+        if self.row + 1 < surface.rows {
+            self.row += 1;
+            Ok(())
+        } else {
+            self.scroll(surface)
+        }
+    }
+
+    // 光标换位置之前先把没上屏的画掉,行缓冲只对得上光标走过的那一段
+    fn move_to(&mut self, surface: &mut Surface, col: usize, row: usize) -> Result {
+        self.flush(surface)?;
+        self.col = col.min(surface.cols - 1);
+        self.row = row.min(surface.rows - 1);
+        Ok(())
+    }
+
+    // 把行缓冲里还没上屏的那段画出去
+    fn flush(&mut self, surface: &mut Surface) -> Result {
+        let Some(start) = self.dirty.take() else { return Ok(()) };
+        if self.col <= start { return Ok(()) }
+        let (cw, ch) = surface.cell;
+        let stride = self.line.len() / ch;
+        let (x, w) = (start * cw, (self.col - start) * cw);
+        blit_region(surface.gop, surface.rotation, &self.line, (x, stride), (x, self.row * ch, w, ch))
+    }
+
+    // 屏幕上的行整体上移一行,用 VideoToVideo 在显存里搬,最后一行清成背景色
+    fn scroll(&mut self, surface: &mut Surface) -> Result {
+        let (width, rows, ch) = (surface.size.0, surface.rows, surface.cell.1);
+        if rows > 1 {
+            let phys = surface.gop.current_mode_info().resolution();
+            let moved = (rows - 1) * ch;
+            let (sx, sy, w, h) = surface.rotation.rect_to_physical((0, ch, width, moved), phys);
+            let (dx, dy, _, _) = surface.rotation.rect_to_physical((0, 0, width, moved), phys);
+            surface.gop.blt(BltOp::VideoToVideo { src: (sx, sy), dest: (dx, dy), dims: (w, h) })?;
+        }
+        self.row = rows - 1;
+        surface.fill((0, self.row * ch, width, ch), self.colors().1)
+    }
+
+    // ESC [ 之后的字符:数字和 ; 是参数,0x40-0x7E 结束
+    fn csi(&mut self, surface: &mut Surface, c: char) -> Result {
+        match c {
+            '0'..='9' => {
+                let p = &mut self.params[self.count];
+                *p = p.saturating_mul(10).saturating_add(c as u16 - '0' as u16);
+            }
+            ';' => self.count = (self.count + 1).min(MAX_PARAMS - 1),
+            '<'..='?' => self.private = true,
+            '\x40'..='\x7e' => {
+                self.state = State::Ground;
+                if !self.private { self.execute(surface, c)? }
+            }
+            // 中间字符不认,等结束字符
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn execute(&mut self, surface: &mut Surface, command: char) -> Result {
+        let params = self.params;
+        let params = &params[..=self.count];
+        // 移动类的参数省略或者为 0 都按 1 算
+        let n = params[0].max(1) as usize;
+        let (col, row) = (self.col, self.row);
+        match command {
+            'm' => self.sgr(params),
+            'H' | 'f' => {
+                let col = params.get(1).map_or(1, |&c| c.max(1)) as usize;
+                self.move_to(surface, col - 1, n - 1)?;
+            }
+            'A' => self.move_to(surface, col, row.saturating_sub(n))?,
+            'B' => self.move_to(surface, col, row + n)?,
+            'C' => self.move_to(surface, col + n, row)?,
+            'D' => self.move_to(surface, col.saturating_sub(n), row)?,
+            'G' => self.move_to(surface, n - 1, row)?,
+            'J' => {
+                self.flush(surface)?;
+                let (width, height) = surface.size;
+                let (bg, ch) = (self.colors().1, surface.cell.1);
+                match params[0] {
+                    0 => {
+                        self.erase_line(surface, col, surface.cols)?;
+                        surface.fill((0, (row + 1) * ch, width, height), bg)?;
+                    }
+                    1 => {
+                        surface.fill((0, 0, width, row * ch), bg)?;
+                        self.erase_line(surface, 0, col + 1)?;
+                    }
+                    _ => surface.fill((0, 0, width, height), bg)?,
+                }
+            }
+            'K' => {
+                self.flush(surface)?;
+                match params[0] {
+                    0 => self.erase_line(surface, col, surface.cols)?,
+                    1 => self.erase_line(surface, 0, col + 1)?,
+                    _ => self.erase_line(surface, 0, surface.cols)?,
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    // 当前行 [from, to) 列清成背景色,清到行尾时连最右边不够一格的零头一起清
+    fn erase_line(&mut self, surface: &mut Surface, from: usize, to: usize) -> Result {
+        let (cw, ch) = surface.cell;
+        let x = from * cw;
+        let w = if to >= surface.cols { surface.size.0.saturating_sub(x) } else { (to - from) * cw };
+        surface.fill((x, self.row * ch, w, ch), self.colors().1)
+    }
+
+    fn sgr(&mut self, params: &[u16]) {
+        let mut i = 0;
+        while i < params.len() {
+            match params[i] {
+                0 => {
+                    (self.fg, self.bg) = (Ink::Default, Ink::Default);
+                    (self.bold, self.reverse) = (false, false);
+                }
+                1 => self.bold = true,
+                22 => self.bold = false,
+                7 => self.reverse = true,
+                27 => self.reverse = false,
+                n @ 30..=37 => self.fg = Ink::Palette((n - 30) as u8),
+                n @ 90..=97 => self.fg = Ink::Palette((n - 90 + 8) as u8),
+                39 => self.fg = Ink::Default,
+                n @ 40..=47 => self.bg = Ink::Palette((n - 40) as u8),
+                n @ 100..=107 => self.bg = Ink::Palette((n - 100 + 8) as u8),
+                49 => self.bg = Ink::Default,
+                n @ (38 | 48) => {
+                    let (ink, used) = extended(&params[i + 1..]);
+                    if let Some(ink) = ink {
+                        if n == 38 { self.fg = ink } else { self.bg = ink }
+                    }
+                    i += used;
+                }
+                _ => {}
+            }
+            i += 1;
+        }
+    }
+}
+
+// 38/48 后面的部分:5;n 或者 2;r;g;b,返回颜色和吃掉的参数个数,认不出来的把剩下的都吃掉
+fn extended(params: &[u16]) -> (Option<Ink>, usize) {
+    match params {
+        [5, n, ..] => (Some(xterm_256(*n as u8)), 2),
+        [2, r, g, b, ..] => (Some(Ink::Rgb(BltPixel::new(*r as u8, *g as u8, *b as u8))), 4),
+        _ => (None, params.len()),
+    }
+}
+
+// 0-15 调色板,16-231 6x6x6 色块,232-255 灰阶
+fn xterm_256(n: u8) -> Ink {
+    const LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+    match n {
+        0..=15 => Ink::Palette(n),
+        16..=231 => {
+            let n = (n - 16) as usize;
+            Ink::Rgb(BltPixel::new(LEVELS[n / 36], LEVELS[n / 6 % 6], LEVELS[n % 6]))
+        }
+        _ => {
+            let level = 8 + (n - 232) * 10;
+            Ink::Rgb(BltPixel::new(level, level, level))
+        }
+    }
+}
+
+fn palette(n: u8) -> BltPixel {
+    let (r, g, b) = PALETTE[n as usize & 15];
+    BltPixel::new(r, g, b)
+}
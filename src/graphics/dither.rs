@@ -0,0 +1,20 @@
+/// 8x8 Bayer 矩阵,0..64
+const BAYER8: [[u8; 8]; 8] = [
+    [ 0, 32,  8, 40,  2, 34, 10, 42],
+    [48, 16, 56, 24, 50, 18, 58, 26],
+    [12, 44,  4, 36, 14, 46,  6, 38],
+    [60, 28, 52, 20, 62, 30, 54, 22],
+    [ 3, 35, 11, 43,  1, 33,  9, 41],
+    [51, 19, 59, 27, 49, 17, 57, 25],
+    [15, 47,  7, 39, 13, 45,  5, 37],
+    [63, 31, 55, 23, 61, 29, 53, 21],
+];
+
+/// 有序抖动:把 8bit 分量量化到 bits 位的档位 [0, 2^bits - 1]
+/// 量化前按屏幕位置加上 [0, 1) 档的阈值,8x8 块的平均值正好还原原值
+/// 大面积渐变不再出现色带,纯白也能落在最高档
+#[inline(always)]
+pub fn dither(value: u8, bits: u32, x: usize, y: usize) -> u32 {
+    let max = (1u32 << bits) - 1;
+    (value as u32 * max * 64 + BAYER8[y & 7][x & 7] as u32 * 255) / (255 * 64)
+}
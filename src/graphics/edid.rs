@@ -0,0 +1,44 @@
+use core::slice;
+use uefi::boot::{self, OpenProtocolAttributes, OpenProtocolParams};
+use uefi::proto::unsafe_protocol;
+use uefi::Handle;
+
+/// EFI_EDID_ACTIVE_PROTOCOL,uefi 库里没有,按规范自己声明
+/// source: UEFI 2.10 12.9.3
+#[repr(C)]
+#[unsafe_protocol("bd8c1056-9f36-44ec-92a8-a6337f817986")]
+pub struct EdidActive {
+    size: u32,
+    edid: *const u8,
+}
+
+impl EdidActive {
+    pub fn bytes(&self) -> &[u8] {
+        if self.edid.is_null() { return &[] }
+        unsafe { slice::from_raw_parts(self.edid, self.size as usize) }
+    }
+}
+
+/// 第一个详细时序描述符里的分辨率和刷新率(毫赫兹)
+/// 一般就是显示器的首选模式
+pub fn preferred_timing(edid: &[u8]) -> Option<((usize, usize), u32)> {
+    if edid.len() < 128 || edid[..8] != [0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x00] { return None }
+    let d = &edid[54..72];
+    // 像素时钟单位 10kHz,为 0 表示不是时序描述符
+    let clock = u16::from_le_bytes([d[0], d[1]]) as u64 * 10_000;
+    if clock == 0 { return None }
+    let h_active = d[2] as usize | ((d[4] as usize & 0xF0) << 4);
+    let h_blank = d[3] as usize | ((d[4] as usize & 0x0F) << 8);
+    let v_active = d[5] as usize | ((d[7] as usize & 0xF0) << 4);
+    let v_blank = d[6] as usize | ((d[7] as usize & 0x0F) << 8);
+    let total = ((h_active + h_blank) * (v_active + v_blank)) as u64;
+    if total == 0 { return None }
+    Some(((h_active, v_active), (clock * 1000 / total) as u32))
+}
+
+/// 从 GOP 所在句柄上读 EDID,只借用不独占,免得把显卡驱动断开
+pub fn read_preferred_timing(gop_handle: Handle) -> Option<((usize, usize), u32)> {
+    let params = OpenProtocolParams { handle: gop_handle, agent: boot::image_handle(), controller: None };
+    let edid = unsafe { boot::open_protocol::<EdidActive>(params, OpenProtocolAttributes::GetProtocol) }.ok()?;
+    preferred_timing(edid.bytes())
+}
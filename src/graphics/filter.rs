@@ -0,0 +1,265 @@
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, Ordering};
+use uefi::proto::console::gop::BltPixel;
+
+/// 单个调色操作,按配置顺序串起来
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColorFilter {
+    /// 亮度偏移 -255..=255
+    Brightness(i16),
+    /// 对比度倍数,1.0 不变
+    Contrast(f32),
+    /// 伽马,1.0 不变,大于 1 变亮
+    Gamma(f32),
+    Grayscale,
+    Invert,
+    Sepia,
+    /// 色温,正数偏暖负数偏冷 -255..=255
+    Temperature(i16),
+}
+
+impl ColorFilter {
+    /// 配置里的写法:grayscale invert sepia,带参数的写成 gamma:1.2 这样
+    pub fn from_name(name: &str) -> Option<Self> {
+        let (name, arg) = match name.split_once(':') {
+            Some((name, arg)) => (name.trim(), Some(arg.trim())),
+            None => (name.trim(), None),
+        };
+        let level = || arg?.parse::<i16>().ok().filter(|v| (-255..=255).contains(v));
+        let factor = || arg?.parse::<f32>().ok().filter(|v| *v > 0.0);
+        Some(match (name.to_ascii_lowercase().as_str(), arg) {
+            ("grayscale", None) => Self::Grayscale,
+            ("invert", None) => Self::Invert,
+            ("sepia", None) => Self::Sepia,
+            ("brightness", _) => Self::Brightness(level()?),
+            ("temperature", _) => Self::Temperature(level()?),
+            ("contrast", _) => Self::Contrast(factor()?),
+            ("gamma", _) => Self::Gamma(factor()?),
+            _ => return None,
+        })
+    }
+}
+
+// 编译后的一步:逐通道查表 或 3x3 颜色矩阵(8.8 定点),表有 768 字节,放堆上
+enum Stage {
+    Lut(Box<Lut>),
+    Matrix([[i32; 3]; 3]),
+}
+
+// 红绿蓝三张表
+type Lut = [[u8; 256]; 3];
+
+const IDENTITY: Lut = {
+    let mut lut = [[0u8; 256]; 3];
+    let mut i = 0;
+    while i < 256 {
+        lut[0][i] = i as u8;
+        lut[1][i] = i as u8;
+        lut[2][i] = i as u8;
+        i += 1;
+    }
+    lut
+};
+
+/// 编译好的滤镜链,只读,所有核心共用
+/// 相邻的逐通道操作合并成一张表,遇到混合通道的矩阵操作才断开
+pub struct FilterChain {
+    stages: Vec<Stage>,
+    enabled: AtomicBool,
+}
+
+impl FilterChain {
+    pub fn new(filters: &[ColorFilter], enabled: bool) -> Self {
+        let mut stages = Vec::new();
+        let mut lut = IDENTITY;
+        let mut dirty = false;
+
+        for filter in filters {
+            let matrix = match *filter {
+                ColorFilter::Grayscale => [[77, 150, 29]; 3],
+                ColorFilter::Sepia => [[101, 197, 48], [89, 176, 43], [70, 137, 34]],
+                per_channel => {
+                    for (ch, table) in lut.iter_mut().enumerate() {
+                        for v in table.iter_mut() {
+                            *v = map_channel(per_channel, ch, *v);
+                        }
+                    }
+                    dirty = true;
+                    continue;
+                }
+            };
+            if dirty {
+                stages.push(Stage::Lut(Box::new(lut)));
+                lut = IDENTITY;
+                dirty = false;
+            }
+            stages.push(Stage::Matrix(matrix));
+        }
+        if dirty { stages.push(Stage::Lut(Box::new(lut))) }
+
+        Self { stages, enabled: AtomicBool::new(enabled) }
+    }
+
+    /// 当前是否需要处理,没有滤镜或者被关掉时为 false
+    #[inline(always)]
+    pub fn active(&self) -> bool {
+        !self.stages.is_empty() && self.enabled.load(Ordering::Relaxed)
+    }
+
+    /// 运行时开关,返回切换后的状态
+    pub fn toggle(&self) -> bool {
+        !self.enabled.fetch_xor(true, Ordering::Relaxed)
+    }
+
+    #[inline(always)]
+    pub fn apply(&self, mut p: BltPixel) -> BltPixel {
+        for stage in &self.stages {
+            p = match stage {
+                Stage::Lut(lut) => BltPixel::new(lut[0][p.red as usize], lut[1][p.green as usize], lut[2][p.blue as usize]),
+                Stage::Matrix(m) => {
+                    let (r, g, b) = (p.red as i32, p.green as i32, p.blue as i32);
+                    let mix = |row: &[i32; 3]| ((row[0] * r + row[1] * g + row[2] * b) >> 8).clamp(0, 255) as u8;
+                    BltPixel::new(mix(&m[0]), mix(&m[1]), mix(&m[2]))
+                }
+            };
+        }
+        p
+    }
+
+    pub fn apply_slice(&self, pixels: &mut [BltPixel]) {
+        if !self.active() { return }
+        for p in pixels.iter_mut() {
+            *p = self.apply(*p);
+        }
+    }
+}
+
+// 单通道映射,ch: 0 红 1 绿 2 蓝
+fn map_channel(filter: ColorFilter, ch: usize, v: u8) -> u8 {
+    let x = v as i32;
+    let y = match filter {
+        ColorFilter::Brightness(d) => x + d as i32,
+        ColorFilter::Contrast(k) => ((x - 128) as f32 * k) as i32 + 128,
+        ColorFilter::Gamma(g) if g > 0.0 => (powf(x as f64 / 255.0, 1.0 / g as f64) * 255.0 + 0.5) as i32,
+        ColorFilter::Invert => 255 - x,
+        ColorFilter::Temperature(t) => match ch {
+            0 => x + t as i32,
+            2 => x - t as i32,
+            _ => x,
+        },
+        _ => x,
+    };
+    y.clamp(0, 255) as u8
+}
+
+// no_std 下没有 powf,只在建表时用,精度够就行
+// x^e = exp(e * ln x),x 在 [0, 1]
+fn powf(x: f64, e: f64) -> f64 {
+    if x <= 0.0 { return 0.0 }
+    exp(e * ln(x))
+}
+
+fn ln(x: f64) -> f64 {
+    // x = m * 2^k,m 在 [1, 2)
+    let bits = x.to_bits();
+    let k = ((bits >> 52) & 0x7FF) as i64 - 1023;
+    let m = f64::from_bits((bits & !(0x7FF << 52)) | (1023 << 52));
+    // ln m = 2 atanh((m - 1) / (m + 1))
+    let z = (m - 1.0) / (m + 1.0);
+    let z2 = z * z;
+    let (mut term, mut sum) = (z, 0.0);
+    for n in 0..12 {
+        sum += term / (2 * n + 1) as f64;
+        term *= z2;
+    }
+    2.0 * sum + k as f64 * core::f64::consts::LN_2
+}
+
+fn exp(y: f64) -> f64 {
+    // y = k ln2 + r,r 在 [0, ln2)
+    let mut k = (y / core::f64::consts::LN_2) as i64;
+    if (k as f64) * core::f64::consts::LN_2 > y { k -= 1 }
+    if k < -1022 { return 0.0 }
+    let r = y - k as f64 * core::f64::consts::LN_2;
+    let (mut term, mut sum) = (1.0, 1.0);
+    for n in 1..16 {
+        term *= r / n as f64;
+        sum += term;
+    }
+    sum * f64::from_bits(((k + 1023) as u64) << 52)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gray(v: u8) -> BltPixel { BltPixel::new(v, v, v) }
+
+    fn channels(p: BltPixel) -> [u8; 3] { [p.red, p.green, p.blue] }
+
+    #[test]
+    fn math_matches_std() {
+        for i in 1..=255 {
+            let x = i as f64 / 255.0;
+            assert!((ln(x) - x.ln()).abs() < 1e-9, "ln {}", x);
+            assert!((exp(-3.0 * x) - (-3.0 * x).exp()).abs() < 1e-9, "exp {}", x);
+            assert!((powf(x, 1.0 / 2.2) - x.powf(1.0 / 2.2)).abs() < 1e-9, "powf {}", x);
+        }
+        assert_eq!(powf(0.0, 0.5), 0.0);
+    }
+
+    #[test]
+    fn gamma_one_is_identity() {
+        let chain = FilterChain::new(&[ColorFilter::Gamma(1.0)], true);
+        for v in 0..=255 {
+            assert_eq!(channels(chain.apply(gray(v))), [v; 3]);
+        }
+    }
+
+    #[test]
+    fn gamma_and_contrast_match_reference() {
+        let gamma = FilterChain::new(&[ColorFilter::Gamma(2.2)], true);
+        let contrast = FilterChain::new(&[ColorFilter::Contrast(1.5)], true);
+        for v in 0..=255u8 {
+            let g = ((v as f64 / 255.0).powf(1.0 / 2.2) * 255.0).round() as i32;
+            assert!((gamma.apply(gray(v)).red as i32 - g).abs() <= 1, "gamma {}", v);
+            let c = ((v as f64 - 128.0) * 1.5 + 128.0).clamp(0.0, 255.0) as i32;
+            assert!((contrast.apply(gray(v)).red as i32 - c).abs() <= 1, "contrast {}", v);
+        }
+        // 几个手算的点
+        assert_eq!(gamma.apply(gray(128)).red, 186);
+        assert_eq!(contrast.apply(gray(100)).red, 86);
+        assert_eq!(contrast.apply(gray(128)).red, 128);
+    }
+
+    // 相邻的逐通道操作合成一张表,和一个个单独做结果一样
+    #[test]
+    fn merged_lut_equals_sequential() {
+        let filters = [
+            ColorFilter::Brightness(20),
+            ColorFilter::Gamma(1.5),
+            ColorFilter::Temperature(-30),
+            ColorFilter::Grayscale,
+            ColorFilter::Contrast(1.2),
+            ColorFilter::Invert,
+        ];
+        let merged = FilterChain::new(&filters, true);
+        assert_eq!(merged.stages.len(), 3);
+        let singles: Vec<FilterChain> = filters.iter().map(|f| FilterChain::new(&[*f], true)).collect();
+        for v in (0..=255u8).step_by(3) {
+            let p = BltPixel::new(v, 255 - v, v / 2);
+            let expected = singles.iter().fold(p, |p, chain| chain.apply(p));
+            assert_eq!(channels(merged.apply(p)), channels(expected), "{}", v);
+        }
+    }
+
+    #[test]
+    fn toggle_disables_chain() {
+        let chain = FilterChain::new(&[ColorFilter::Invert], true);
+        assert!(chain.active());
+        assert!(!chain.toggle());
+        assert!(!chain.active());
+        assert!(!FilterChain::new(&[], true).active());
+    }
+}
@@ -0,0 +1,448 @@
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::convert::Infallible;
+use core::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+use embedded_graphics::pixelcolor::Rgb888;
+use embedded_graphics::prelude::{DrawTarget, OriginDimensions, Pixel, Point, RgbColor, Size};
+use u8g2_fonts::types::{FontColor, VerticalPosition};
+use u8g2_fonts::{fonts, FontRenderer};
+use uefi::proto::console::gop::BltPixel;
+use crate::config::Config;
+use crate::error::{NyaStatus, Result};
+use crate::fs::{to_path, Fs};
+use crate::video::ascii_font::FONT_8X16;
+
+// 没有加载字体时的字符格,和 FONT_8X16 一样
+const BUILTIN_W: usize = 8;
+const BUILTIN_H: usize = 16;
+
+// 自动放大时希望字高大约是屏幕短边的几分之一,1080p 上 16 像素的字不放大,4K 上放大 3 倍
+const AUTO_LINES: usize = 45;
+
+// 从磁盘加载的字体,启动时在 BSP 上装一次,之后只读,空指针为没有
+static FONT: AtomicPtr<BitmapFont> = AtomicPtr::new(core::ptr::null_mut());
+// 整数倍放大
+static SCALE: AtomicUsize = AtomicUsize::new(1);
+
+// 加载的字体里没有的非 ASCII 字符按顺序找,都是 16 像素高的点阵,画在字符格左上角
+// unifont 的西文半角,文泉驿的简体,unifont 的假名和日文汉字,最后是韩文
+static FALLBACK: [FontRenderer; 4] = [
+    FontRenderer::new::<fonts::u8g2_font_unifont_t_extended>(),
+    FontRenderer::new::<fonts::u8g2_font_wqy16_t_gb2312>(),
+    FontRenderer::new::<fonts::u8g2_font_unifont_t_japanese3>(),
+    FontRenderer::new::<fonts::u8g2_font_unifont_t_korean2>(),
+];
+
+/// 占几个字符格,东亚宽字符 2 格,其余 1 格
+/// 范围取自 Unicode EastAsianWidth 里的 W 和 F,只挑了常用的几段
+pub fn width(c: char) -> usize {
+    match c as u32 {
+        0x1100..=0x115F        // 谚文字母
+        | 0x2E80..=0x303E      // 部首、CJK 标点
+        | 0x3041..=0x33FF      // 假名、注音、CJK 兼容
+        | 0x3400..=0x4DBF      // 扩展 A
+        | 0x4E00..=0x9FFF      // 基本汉字
+        | 0xA000..=0xA4CF      // 彝文
+        | 0xAC00..=0xD7A3      // 谚文音节
+        | 0xF900..=0xFAFF      // 兼容汉字
+        | 0xFE30..=0xFE4F      // 竖排标点
+        | 0xFF00..=0xFF60      // 全角 ASCII
+        | 0xFFE0..=0xFFE6
+        | 0x20000..=0x3FFFD => 2,
+        _ => 1,
+    }
+}
+
+/// 整串占几个字符格
+pub fn text_width(text: &str) -> usize {
+    text.chars().map(width).sum()
+}
+
+/// 最长的能放进 cols 格的前缀
+pub fn clip(text: &str, cols: usize) -> &str {
+    let mut used = 0;
+    for (i, c) in text.char_indices() {
+        used += width(c);
+        if used > cols { return &text[..i] }
+    }
+    text
+}
+
+/// 按配置加载字体并定下放大倍数,short_side 为屏幕短边,自动放大时用
+/// 要在 BSP 上、启动 AP 之前调用
+pub fn configure(fs: &mut Fs, config: &Config, short_side: usize) -> Result {
+    if let Some(path) = &config.font {
+        let font = BitmapFont::load(fs, path)?;
+        log::info!("font: {} {}x{}, {} glyphs", path, font.width, font.height, font.map.len());
+        // 一直用到退出,不释放
+        FONT.store(Box::leak(Box::new(font)), Ordering::Release);
+    }
+    let scale = match config.font_scale {
+        0 => (short_side / AUTO_LINES / unscaled().1).max(1),
+        n => n,
+    };
+    SCALE.store(scale, Ordering::Relaxed);
+    Ok(())
+}
+
+/// 一个窄字符格放大以后的像素大小,宽字符占两格
+pub fn cell() -> (usize, usize) {
+    let (w, h) = unscaled();
+    let scale = scale();
+    (w * scale, h * scale)
+}
+
+fn scale() -> usize { SCALE.load(Ordering::Relaxed) }
+
+fn loaded() -> Option<&'static BitmapFont> {
+    unsafe { FONT.load(Ordering::Acquire).as_ref() }
+}
+
+fn unscaled() -> (usize, usize) {
+    loaded().map_or((BUILTIN_W, BUILTIN_H), |font| (font.width, font.height))
+}
+
+/// 把 c 的笔画用 fg 画到 buf 的 (x, 0) 处,buf 每行 stride 个像素,不碰背景
+pub fn draw_glyph(buf: &mut [BltPixel], stride: usize, x: usize, c: char, fg: BltPixel) {
+    glyph(c, |dx, dy| buf[dy * stride + x + dx] = fg);
+}
+
+/// 对 c 的每个笔画点调用 plot(x, y),坐标相对字符格左上角,已经放大过
+/// 先找加载的字体,没有的字用内置的,都画在 width(c) 个字符格以内,哪个字体都没有的字画成 ?
+pub fn glyph(c: char, mut plot: impl FnMut(usize, usize)) {
+    let scale = scale();
+    let (w, h) = unscaled();
+    let w = width(c) * w;
+    let mut plot = |x: usize, y: usize| {
+        if x >= w || y >= h { return }
+        for dy in 0..scale {
+            for dx in 0..scale { plot(x * scale + dx, y * scale + dy) }
+        }
+    };
+    if loaded().is_some_and(|font| font.draw(c, &mut plot)) { return }
+
+    if c.is_ascii() {
+        builtin(c, &mut plot);
+        return;
+    }
+
+    let mut cell = Cell { plot: &mut plot, size: (w, h) };
+    let color = FontColor::Transparent(Rgb888::WHITE);
+    let found = FALLBACK.iter().any(|font| {
+        font.render(c, Point::zero(), VerticalPosition::Top, color, &mut cell).is_ok()
+    });
+    if !found { builtin('?', &mut plot) }
+}
+
+fn builtin(c: char, plot: &mut impl FnMut(usize, usize)) {
+    let glyph = &FONT_8X16[c as usize & 0x7F];
+    for (row, bits) in glyph.iter().enumerate() {
+        for col in 0..8 {
+            if (bits << col) & 0x80 != 0 { plot(col, row) }
+        }
+    }
+}
+
+// 一个字符格当作 embedded-graphics 的画布,出格的点丢掉,只关心点在哪
+struct Cell<'a> {
+    plot: &'a mut dyn FnMut(usize, usize),
+    size: (usize, usize),
+}
+
+impl OriginDimensions for Cell<'_> {
+    fn size(&self) -> Size { Size::new(self.size.0 as u32, self.size.1 as u32) }
+}
+
+impl DrawTarget for Cell<'_> {
+    type Color = Rgb888;
+    type Error = Infallible;
+
+    fn draw_iter<I: IntoIterator<Item = Pixel<Rgb888>>>(&mut self, pixels: I) -> core::result::Result<(), Infallible> {
+        for Pixel(p, _) in pixels {
+            let (x, y) = (p.x as usize, p.y as usize);
+            // 负数转过来是很大的数,一起判掉
+            if x < self.size.0 && y < self.size.1 { (self.plot)(x, y) }
+        }
+        Ok(())
+    }
+}
+
+/// 从磁盘读进来的点阵字体,PSF2 或者 BDF
+/// 所有字形按同样大小的格子存,每行高位在左;BDF 里的宽字符存两格宽,PSF2 只有一格
+pub struct BitmapFont {
+    /// 窄字符格的大小,像素
+    pub width: usize,
+    pub height: usize,
+    // 每个字形存多宽
+    glyph_width: usize,
+    glyphs: Vec<u8>,
+    // 字符到第几个字形
+    map: BTreeMap<char, usize>,
+}
+
+impl BitmapFont {
+    /// 按文件头认格式,写错了报出原因,BDF 带行号
+    pub fn load(fs: &mut Fs, path: &str) -> Result<Self> {
+        let data = fs.read_file(&to_path(path)?)?;
+        let bad = |msg: String| NyaStatus::_Debug(format!("{}: {}", path, msg));
+        if data.starts_with(&PSF2_MAGIC) {
+            Self::psf2(&data).map_err(bad)
+        } else if data.starts_with(b"STARTFONT") {
+            let text = core::str::from_utf8(&data).map_err(|_| bad("not valid UTF-8".to_string()))?;
+            Self::bdf(text).map_err(|(no, msg)| bad(format!("{}: {}", no, msg)))
+        } else {
+            Err(bad("not a PSF2 or BDF font".to_string()))
+        }
+    }
+
+    // 头 32 字节全是小端 u32:magic、版本、头大小、flags、字形数、每字形字节数、高、宽
+    // flags 第 0 位表示字形后面跟着 Unicode 表,每个字形一串 UTF-8,0xFE 以后是组合序列,0xFF 结束
+    fn psf2(data: &[u8]) -> core::result::Result<Self, String> {
+        let field = |i: usize| data.get(4 * i..4 * i + 4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize);
+        let [Some(header), Some(flags), Some(count), Some(size), Some(height), Some(width)] = [2, 3, 4, 5, 6, 7].map(field) else {
+            return Err("truncated header".to_string());
+        };
+        if width == 0 || height == 0 || size != width.div_ceil(8) * height {
+            return Err(format!("bad glyph size {}x{} in {} bytes", width, height, size));
+        }
+        let end = count.checked_mul(size).and_then(|n| n.checked_add(header)).filter(|&end| end <= data.len())
+            .ok_or("truncated glyph data")?;
+        let glyphs = data[header..end].to_vec();
+
+        let mut map = BTreeMap::new();
+        if flags & 1 != 0 {
+            let mut table = &data[end..];
+            for index in 0..count {
+                let stop = table.iter().position(|&b| b == 0xFF).ok_or("truncated unicode table")?;
+                // 组合序列不认,只要前面的单个字符
+                let single = table[..stop].split(|&b| b == 0xFE).next().unwrap_or(&[]);
+                let single = core::str::from_utf8(single).map_err(|_| format!("bad UTF-8 in unicode table at glyph {}", index))?;
+                for c in single.chars() { map.entry(c).or_insert(index); }
+                table = &table[stop + 1..];
+            }
+        } else {
+            // 没有表就按字形序号当码点
+            map.extend((0..count).filter_map(|i| char::from_u32(i as u32).map(|c| (c, i))));
+        }
+        Ok(Self { width, height, glyph_width: width, glyphs, map })
+    }
+
+    // 先把每个字的 BBX 和位图读出来,整个字体的格子大小定下来以后再按基线摆进格子
+    // 格子高取 FONTBOUNDINGBOX,窄格宽取 M 的 DWIDTH;出错返回 (行号, 原因)
+    fn bdf(text: &str) -> core::result::Result<Self, (usize, String)> {
+        let mut bounds = None;
+        let mut ascent = None;
+        let mut chars = Vec::new();
+        let mut current: Option<BdfChar> = None;
+        let mut lines = text.lines().enumerate().map(|(no, line)| (no + 1, line.trim()));
+        while let Some((no, line)) = lines.next() {
+            let (key, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let bad = |what: &str| (no, format!("invalid {} '{}'", what, rest.trim()));
+            match (key, &mut current) {
+                ("FONTBOUNDINGBOX", None) => bounds = Some(numbers::<4>(rest).ok_or_else(|| bad("bounding box"))?),
+                ("FONT_ASCENT", None) => ascent = Some(numbers::<1>(rest).ok_or_else(|| bad("ascent"))?[0]),
+                ("STARTCHAR", None) => current = Some(BdfChar::default()),
+                ("ENCODING", Some(ch)) => ch.code = numbers::<1>(rest).ok_or_else(|| bad("encoding"))?[0],
+                ("DWIDTH", Some(ch)) => ch.advance = numbers::<1>(rest).ok_or_else(|| bad("width"))?[0],
+                ("BBX", Some(ch)) => ch.bbx = numbers::<4>(rest).ok_or_else(|| bad("bounding box"))?,
+                ("BITMAP", Some(ch)) => {
+                    let bytes = (ch.bbx[0].max(0) as usize).div_ceil(8);
+                    for _ in 0..ch.bbx[1].max(0) {
+                        let (no, hex) = lines.next().ok_or((no, "truncated bitmap".to_string()))?;
+                        let row = (0..bytes).map(|i| hex.get(2 * i..2 * i + 2).and_then(|b| u8::from_str_radix(b, 16).ok()))
+                            .collect::<Option<Vec<u8>>>()
+                            .ok_or_else(|| (no, format!("invalid bitmap row '{}'", hex)))?;
+                        ch.rows.push(row);
+                    }
+                }
+                // 没有 BITMAP 或者行数不够的字不能当空白字收下
+                ("ENDCHAR", Some(ch)) if ch.rows.len() != ch.bbx[1].max(0) as usize => return Err((no, "missing BITMAP".to_string())),
+                ("ENDCHAR", Some(_)) => chars.extend(current.take()),
+                ("STARTCHAR", Some(_)) | ("ENDCHAR", None) => return Err((no, format!("unexpected {}", key))),
+                _ => {}
+            }
+        }
+
+        let [bw, bh, _, by] = bounds.ok_or((0, "missing FONTBOUNDINGBOX".to_string()))?;
+        if bw <= 0 || bh <= 0 { return Err((0, "empty FONTBOUNDINGBOX".to_string())) }
+        let height = bh as usize;
+        let ascent = ascent.unwrap_or(bh + by);
+        let width = chars.iter().find(|ch| ch.code == 'M' as i32).map_or(bw, |ch| ch.advance).max(1) as usize;
+        let glyph_width = if chars.iter().any(|ch| ch.advance as usize > width) { 2 * width } else { width };
+
+        let row_bytes = glyph_width.div_ceil(8);
+        let mut glyphs = vec![0u8; chars.len() * height * row_bytes];
+        let mut map = BTreeMap::new();
+        for (index, ch) in chars.iter().enumerate() {
+            // ENCODING -1 是没有码点的字形
+            let Some(c) = u32::try_from(ch.code).ok().and_then(char::from_u32) else { continue };
+            map.insert(c, index);
+            let glyph = &mut glyphs[index * height * row_bytes..][..height * row_bytes];
+            let [w, h, ox, oy] = ch.bbx;
+            let top = ascent - oy - h;
+            for (r, row) in ch.rows.iter().enumerate() {
+                let y = top + r as i32;
+                if y < 0 || y as usize >= height { continue }
+                for col in 0..w {
+                    let x = ox + col;
+                    if x < 0 || x as usize >= glyph_width { continue }
+                    if row[col as usize / 8] & (0x80 >> (col % 8)) != 0 {
+                        glyph[y as usize * row_bytes + x as usize / 8] |= 0x80 >> (x % 8);
+                    }
+                }
+            }
+        }
+        Ok(Self { width, height, glyph_width, glyphs, map })
+    }
+
+    // 有这个字就对每个笔画点调用 plot,坐标未放大
+    fn draw(&self, c: char, plot: &mut impl FnMut(usize, usize)) -> bool {
+        let Some(&index) = self.map.get(&c) else { return false };
+        let row_bytes = self.glyph_width.div_ceil(8);
+        let glyph = &self.glyphs[index * self.height * row_bytes..][..self.height * row_bytes];
+        let w = (width(c) * self.width).min(self.glyph_width);
+        for (y, row) in glyph.chunks_exact(row_bytes).enumerate() {
+            for x in 0..w {
+                if row[x / 8] & (0x80 >> (x % 8)) != 0 { plot(x, y) }
+            }
+        }
+        true
+    }
+}
+
+const PSF2_MAGIC: [u8; 4] = [0x72, 0xB5, 0x4A, 0x86];
+
+// BDF 里一个字,还没摆进格子
+#[derive(Default)]
+struct BdfChar {
+    code: i32,
+    advance: i32,
+    // 宽、高、左下角相对原点的 x、y
+    bbx: [i32; 4],
+    rows: Vec<Vec<u8>>,
+}
+
+// 空格分开的 N 个整数,多出来的不管
+fn numbers<const N: usize>(text: &str) -> Option<[i32; N]> {
+    let mut parts = text.split_whitespace();
+    let mut out = [0; N];
+    for n in out.iter_mut() { *n = parts.next()?.parse().ok()? }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 8x2 的 PSF2,每个字形 2 字节
+    fn psf2_font(flags: u32, count: u32, glyphs: &[u8], table: &[u8]) -> Vec<u8> {
+        let mut data = PSF2_MAGIC.to_vec();
+        for n in [0, 32, flags, count, 2, 2, 8] { data.extend(u32::to_le_bytes(n)) }
+        data.extend(glyphs);
+        data.extend(table);
+        data
+    }
+
+    const BDF: &str = "STARTFONT 2.1
+FONT -test-fixed-medium-r-normal--2-20-75-75-c-80-iso10646-1
+FONTBOUNDINGBOX 8 2 0 0
+STARTPROPERTIES 1
+FONT_ASCENT 2
+ENDPROPERTIES
+CHARS 2
+STARTCHAR M
+ENCODING 77
+DWIDTH 8 0
+BBX 8 2 0 0
+BITMAP
+FF
+81
+ENDCHAR
+STARTCHAR uni6C49
+ENCODING 27721
+DWIDTH 16 0
+BBX 16 2 0 0
+BITMAP
+8001
+FFFF
+ENDCHAR
+ENDFONT
+";
+
+    fn points(font: &BitmapFont, c: char) -> Option<Vec<(usize, usize)>> {
+        let mut out = Vec::new();
+        font.draw(c, &mut |x, y| out.push((x, y))).then_some(out)
+    }
+
+    #[test]
+    fn psf2_unicode_table() {
+        // 第二个字形后面跟着组合序列,只认前面的 é
+        let table = b"A\xFF\xC3\xA9\xFEe\xCC\x81\xFF";
+        let font = BitmapFont::psf2(&psf2_font(1, 2, &[0x80, 0x01, 0xFF, 0x00], table)).unwrap();
+        assert_eq!((font.width, font.height), (8, 2));
+        assert_eq!(points(&font, 'A'), Some(vec![(0, 0), (7, 1)]));
+        assert_eq!(points(&font, 'é').map(|p| p.len()), Some(8));
+        assert_eq!(points(&font, 'e'), None);
+    }
+
+    #[test]
+    fn psf2_without_table_maps_by_index() {
+        let font = BitmapFont::psf2(&psf2_font(0, 2, &[0x80, 0x01, 0xFF, 0x00], &[])).unwrap();
+        assert_eq!(points(&font, '\u{0}'), Some(vec![(0, 0), (7, 1)]));
+        assert_eq!(points(&font, '\u{1}').map(|p| p.len()), Some(8));
+        assert_eq!(points(&font, '\u{2}'), None);
+    }
+
+    #[test]
+    fn psf2_rejects_broken_files() {
+        let good = psf2_font(1, 2, &[0x80, 0x01, 0xFF, 0x00], b"A\xFFB\xFF");
+        assert!(BitmapFont::psf2(&good).is_ok());
+        // 头被截断
+        for len in [0, 4, 20, 31] {
+            assert_eq!(BitmapFont::psf2(&good[..len]).err().as_deref(), Some("truncated header"), "len {}", len);
+        }
+        // 字形数据不够
+        assert!(BitmapFont::psf2(&psf2_font(0, 2, &[0x80, 0x01, 0xFF], &[])).is_err());
+        // Unicode 表少了结尾的 0xFF
+        assert!(BitmapFont::psf2(&good[..good.len() - 1]).is_err());
+        // 字形数大到乘起来溢出
+        assert!(BitmapFont::psf2(&psf2_font(0, u32::MAX, &[], &[])).is_err());
+        // 每字形字节数和宽高对不上
+        let mut bad = good.clone();
+        bad[20] = 3;
+        assert!(BitmapFont::psf2(&bad).is_err());
+    }
+
+    #[test]
+    fn bdf_places_narrow_and_wide_glyphs() {
+        let font = BitmapFont::bdf(BDF).unwrap();
+        assert_eq!((font.width, font.height, font.glyph_width), (8, 2, 16));
+        let m = points(&font, 'M').unwrap();
+        assert_eq!(m.len(), 10);
+        assert!(m.contains(&(7, 1)) && !m.contains(&(6, 1)));
+        let han = points(&font, '汉').unwrap();
+        assert_eq!(han.len(), 18);
+        assert!(han.contains(&(15, 0)));
+    }
+
+    #[test]
+    fn bdf_rejects_broken_files() {
+        let err = |text: &str| BitmapFont::bdf(text).err();
+
+        // 第二个字没有 BITMAP,报在它的 ENDCHAR 那一行
+        let no_bitmap = BDF.replace("BITMAP\n8001\nFFFF\n", "");
+        assert_eq!(err(&no_bitmap), Some((20, "missing BITMAP".to_string())));
+        // 位图行数不够,文件就结束了
+        let truncated = &BDF[..BDF.find("FFFF").unwrap()];
+        assert_eq!(err(truncated).map(|e| e.1).as_deref(), Some("truncated bitmap"));
+        assert!(err(&BDF.replace("8001", "80G1")).is_some());
+        assert!(err(&BDF.replace("FONTBOUNDINGBOX 8 2 0 0", "")).is_some());
+        assert!(err(&BDF.replace("BBX 8 2 0 0", "BBX 8 x 0 0")).is_some());
+        // ENDCHAR 前又来一个 STARTCHAR
+        assert!(err(&BDF.replacen("ENDCHAR\n", "", 1)).is_some());
+    }
+}
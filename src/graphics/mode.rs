@@ -0,0 +1,35 @@
+use uefi::proto::console::gop::{GraphicsOutput, Mode};
+
+/// 挑选显示模式,优先级:
+/// 1. 配置里指定的分辨率
+/// 2. 和视频分辨率完全一致
+/// 3. 不超过视频分辨率的最大模式(面积)
+/// 4. 都没有就返回 None,保持固件启动时的原生模式
+pub fn choose_mode(gop: &GraphicsOutput, video: (usize, usize), wanted: Option<(usize, usize)>) -> Option<Mode> {
+    if let Some(wanted) = wanted {
+        if let Some(mode) = gop.modes().find(|m| m.info().resolution() == wanted) {
+            return Some(mode);
+        }
+        log::warn!("Configured mode {}x{} is not supported, falling back to auto", wanted.0, wanted.1);
+    }
+
+    if let Some(mode) = gop.modes().find(|m| m.info().resolution() == video) {
+        return Some(mode);
+    }
+
+    gop.modes()
+        .filter(|m| {
+            let (w, h) = m.info().resolution();
+            w <= video.0 && h <= video.1
+        })
+        .max_by_key(|m| {
+            let (w, h) = m.info().resolution();
+            w * h
+        })
+}
+
+/// 找到当前正在使用的模式,GOP 协议没有直接给出当前模式的 Mode
+pub fn current_mode(gop: &GraphicsOutput) -> Option<Mode> {
+    let info = gop.current_mode_info();
+    gop.modes().find(|m| *m.info() == info)
+}
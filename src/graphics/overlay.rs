@@ -0,0 +1,205 @@
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cell::UnsafeCell;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use uefi::proto::console::gop::BltPixel;
+use crate::graphics::rotate::Rotation;
+use crate::graphics::font;
+
+/// 叠加层编号,由 Compositor::add 返回
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PlaneId(usize);
+
+/// 视频上面的一层,矩形,整层一个透明度,可选一个透明色
+///
+/// 像素按物理坐标存,合成时整段拷贝
+/// BSP 随时往 staging 里画,每轮打卡前 sync 一次发布出去
+/// 发布用的两块缓冲按轮次错开:第 r 轮各核读 [r & 1],BSP 写 [(r + 1) & 1]
+pub struct Plane {
+    // 逻辑坐标下的位置和大小
+    origin: (usize, usize),
+    size: (usize, usize),
+    // 物理坐标下的矩形 (x, y, w, h)
+    rect: (usize, usize, usize, usize),
+    alpha: u8,
+    key: Option<BltPixel>,
+    staging: UnsafeCell<Vec<BltPixel>>,
+    staged_version: AtomicU64,
+    staged_visible: AtomicBool,
+    buffers: [UnsafeCell<Vec<BltPixel>>; 2],
+    buffer_version: [AtomicU64; 2],
+    visible: [AtomicBool; 2],
+}
+
+/// 所有叠加层,按添加顺序从下往上叠
+pub struct Compositor {
+    rotation: Rotation,
+    phys: (usize, usize),
+    planes: Vec<Plane>,
+}
+
+// staging 只有 BSP 碰,发布缓冲按轮次错开读写,见 Plane 的说明
+unsafe impl Sync for Compositor {}
+
+impl Compositor {
+    /// phys 为物理分辨率
+    pub fn new(rotation: Rotation, phys: (usize, usize)) -> Self {
+        Self { rotation, phys, planes: Vec::new() }
+    }
+
+    /// 旋转后的屏幕大小
+    #[inline]
+    pub fn logical_size(&self) -> (usize, usize) { self.rotation.logical_size(self.phys) }
+
+    /// rect 为逻辑坐标 (x, y, w, h),超出屏幕的部分裁掉
+    /// alpha 255 不透明;key 为透明色,等于它的像素不画
+    pub fn add(&mut self, (x, y, w, h): (usize, usize, usize, usize), alpha: u8, key: Option<BltPixel>) -> PlaneId {
+        let (lw, lh) = self.logical_size();
+        let (x, y) = (x.min(lw), y.min(lh));
+        let size = (w.min(lw - x), h.min(lh - y));
+        let rect = self.rotation.rect_to_physical((x, y, size.0, size.1), self.phys);
+        let len = rect.2 * rect.3;
+        let fill = key.unwrap_or(BltPixel::new(0, 0, 0));
+        self.planes.push(Plane {
+            origin: (x, y),
+            size,
+            rect,
+            alpha,
+            key,
+            staging: UnsafeCell::new(vec![fill; len]),
+            staged_version: AtomicU64::new(0),
+            staged_visible: AtomicBool::new(false),
+            buffers: [UnsafeCell::new(vec![fill; len]), UnsafeCell::new(vec![fill; len])],
+            buffer_version: [AtomicU64::new(0), AtomicU64::new(0)],
+            visible: [AtomicBool::new(false), AtomicBool::new(false)],
+        });
+        PlaneId(self.planes.len() - 1)
+    }
+
+    /// 往 staging 里画,只能在 BSP 上调用
+    pub fn draw(&self, id: PlaneId, f: impl FnOnce(&mut Canvas)) {
+        let plane = &self.planes[id.0];
+        let mut canvas = Canvas {
+            pixels: unsafe { &mut *plane.staging.get() },
+            rotation: self.rotation,
+            phys: self.phys,
+            origin: plane.origin,
+            size: plane.size,
+            rect: plane.rect,
+        };
+        f(&mut canvas);
+        plane.staged_version.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// 显示或隐藏,下一次 sync 后生效
+    pub fn show(&self, id: PlaneId, visible: bool) {
+        self.planes[id.0].staged_visible.store(visible, Ordering::Relaxed);
+    }
+
+    /// 第 round 轮打卡前在 BSP 上调用,把 staging 发布给下一轮
+    pub fn sync(&self, round: usize) {
+        let next = (round + 1) & 1;
+        for plane in &self.planes {
+            let visible = plane.staged_visible.load(Ordering::Relaxed);
+            let version = plane.staged_version.load(Ordering::Relaxed);
+            if visible && plane.buffer_version[next].load(Ordering::Relaxed) != version {
+                unsafe { (*plane.buffers[next].get()).copy_from_slice(&*plane.staging.get()) }
+                plane.buffer_version[next].store(version, Ordering::Relaxed);
+            }
+            plane.visible[next].store(visible, Ordering::Release);
+        }
+    }
+
+    /// 第 round 轮物理行 y 上有没有可见的叠加层
+    #[inline(always)]
+    pub fn covers(&self, round: usize, y: usize) -> bool {
+        self.planes.iter().any(|p| p.row(round, y).is_some())
+    }
+
+    /// 把第 round 轮的叠加层合成到物理行 y 上,row 为整行
+    #[inline]
+    pub fn blend_row(&self, round: usize, y: usize, row: &mut [BltPixel]) {
+        for plane in &self.planes {
+            let Some((x, src)) = plane.row(round, y) else { continue };
+            let dst = &mut row[x..x + src.len()];
+            match (plane.alpha, plane.key) {
+                (255, None) => dst.copy_from_slice(src),
+                (alpha, key) => {
+                    for (d, s) in dst.iter_mut().zip(src) {
+                        if key.is_some_and(|k| same(k, *s)) { continue }
+                        *d = if alpha == 255 { *s } else { mix(*d, *s, alpha as u32) };
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Plane {
+    #[inline(always)]
+    fn row(&self, round: usize, y: usize) -> Option<(usize, &[BltPixel])> {
+        let (x, top, w, h) = self.rect;
+        if y < top || y >= top + h || !self.visible[round & 1].load(Ordering::Acquire) { return None }
+        let pixels = unsafe { &*self.buffers[round & 1].get() };
+        let start = (y - top) * w;
+        Some((x, &pixels[start..start + w]))
+    }
+}
+
+// BltPixel 没实现 PartialEq,保留字节也不用比
+#[inline(always)]
+fn same(a: BltPixel, b: BltPixel) -> bool {
+    a.red == b.red && a.green == b.green && a.blue == b.blue
+}
+
+#[inline(always)]
+fn mix(dst: BltPixel, src: BltPixel, alpha: u32) -> BltPixel {
+    #[inline(always)]
+    fn ch(d: u8, s: u8, a: u32) -> u8 { ((s as u32 * a + d as u32 * (255 - a) + 127) / 255) as u8 }
+    BltPixel::new(ch(dst.red, src.red, alpha), ch(dst.green, src.green, alpha), ch(dst.blue, src.blue, alpha))
+}
+
+/// 叠加层的画布,坐标是层内的逻辑坐标,旋转在这里处理
+pub struct Canvas<'a> {
+    pixels: &'a mut [BltPixel],
+    rotation: Rotation,
+    phys: (usize, usize),
+    origin: (usize, usize),
+    size: (usize, usize),
+    rect: (usize, usize, usize, usize),
+}
+
+impl Canvas<'_> {
+    #[inline]
+    pub fn size(&self) -> (usize, usize) { self.size }
+
+    #[inline(always)]
+    pub fn put(&mut self, x: usize, y: usize, color: BltPixel) {
+        if x >= self.size.0 || y >= self.size.1 { return }
+        let (px, py) = self.rotation.to_physical(self.origin.0 + x, self.origin.1 + y, self.phys);
+        let (rx, ry, rw, _) = self.rect;
+        self.pixels[(py - ry) * rw + px - rx] = color;
+    }
+
+    pub fn fill(&mut self, x: usize, y: usize, w: usize, h: usize, color: BltPixel) {
+        for yy in y..(y + h).min(self.size.1) {
+            for xx in x..(x + w).min(self.size.0) {
+                self.put(xx, yy, color);
+            }
+        }
+    }
+
+    pub fn clear(&mut self, color: BltPixel) {
+        self.pixels.fill(color);
+    }
+
+    /// 一行点阵字,宽字符占两格,只画前景,返回画到的 x
+    pub fn text(&mut self, x: usize, y: usize, s: &str, color: BltPixel) -> usize {
+        let (mut x, cw) = (x, font::cell().0);
+        for c in s.chars() {
+            font::glyph(c, |dx, dy| self.put(x + dx, y + dy, color));
+            x += font::width(c) * cw;
+        }
+        x
+    }
+}
@@ -0,0 +1,229 @@
+use uefi::proto::console::gop::{BltPixel, ModeInfo, PixelBitmask, PixelFormat};
+use crate::graphics::dither::dither;
+
+/// 单个颜色通道在显存像素里的位置
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Channel {
+    pub shift: u32,
+    pub bits: u32,
+}
+
+impl Channel {
+    // 固件给的掩码默认是连续的,不连续的掩码规范里也没见过
+    fn from_mask(mask: u32) -> Self {
+        if mask == 0 { return Self { shift: 0, bits: 0 } }
+        Self { shift: mask.trailing_zeros(), bits: mask.count_ones() }
+    }
+
+    /// 8bit 分量放进通道,位数不足直接截断高位
+    #[inline(always)]
+    pub fn pack(self, value: u8) -> u32 {
+        match self.bits {
+            0 => 0,
+            bits @ 1..8 => ((value >> (8 - bits)) as u32) << self.shift,
+            bits => (value as u32) << (self.shift + bits - 8),
+        }
+    }
+
+    /// 同 pack,位数不足时按屏幕位置抖动量化
+    #[inline(always)]
+    pub fn pack_dithered(self, value: u8, x: usize, y: usize) -> u32 {
+        match self.bits {
+            1..8 => dither(value, self.bits, x, y) << self.shift,
+            _ => self.pack(value),
+        }
+    }
+}
+
+/// 显存像素布局,由 ModeInfo 推导
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PixelLayout {
+    /// B G R X,和 BltPixel 内存布局一致,可以直接拷贝
+    Bgr,
+    /// R G B X
+    Rgb,
+    /// 任意掩码,bytes 为单像素字节数(565/555 之类只有 2 字节)
+    Bitmask { red: Channel, green: Channel, blue: Channel, bytes: usize },
+    /// 没有线性显存,只能走 blt
+    BltOnly,
+}
+
+impl PixelLayout {
+    pub fn from_mode(info: &ModeInfo) -> Self {
+        match info.pixel_format() {
+            PixelFormat::Bgr => Self::Bgr,
+            PixelFormat::Rgb => Self::Rgb,
+            PixelFormat::BltOnly => Self::BltOnly,
+            PixelFormat::Bitmask => match info.pixel_bitmask() {
+                Some(mask) => Self::from_bitmask(mask),
+                // 按规范不会发生,退回 blt 最稳
+                None => Self::BltOnly,
+            }
+        }
+    }
+
+    pub fn from_bitmask(mask: PixelBitmask) -> Self {
+        let all = mask.red | mask.green | mask.blue | mask.reserved;
+        // 最高位决定像素宽度,至少 1 字节
+        let bytes = (32 - all.leading_zeros() as usize).div_ceil(8).max(1);
+        let (red, green, blue) =
+            (Channel::from_mask(mask.red), Channel::from_mask(mask.green), Channel::from_mask(mask.blue));
+
+        // 其实就是标准格式,走快速路径
+        if bytes == 4 && red.bits == 8 && green.bits == 8 && blue.bits == 8 {
+            match (red.shift, green.shift, blue.shift) {
+                (16, 8, 0) => return Self::Bgr,
+                (0, 8, 16) => return Self::Rgb,
+                _ => {}
+            }
+        }
+        Self::Bitmask { red, green, blue, bytes }
+    }
+
+    #[inline]
+    pub fn has_framebuffer(&self) -> bool { !matches!(self, Self::BltOnly) }
+
+    /// 有通道不足 8 位(565/555 之类),写显存时需要抖动
+    #[inline]
+    pub fn needs_dither(&self) -> bool {
+        match self {
+            Self::Bitmask { red, green, blue, .. } => red.bits < 8 || green.bits < 8 || blue.bits < 8,
+            _ => false,
+        }
+    }
+
+    /// 单像素字节数,BltOnly 按 BltPixel 算
+    #[inline]
+    pub fn bytes_per_pixel(&self) -> usize {
+        match self {
+            Self::Bitmask { bytes, .. } => *bytes,
+            _ => 4,
+        }
+    }
+
+    /// BltPixel -> 显存里的像素值
+    #[inline(always)]
+    pub fn pack(&self, p: BltPixel) -> u32 {
+        match self {
+            Self::Bgr | Self::BltOnly => (p.red as u32) << 16 | (p.green as u32) << 8 | p.blue as u32,
+            Self::Rgb => (p.blue as u32) << 16 | (p.green as u32) << 8 | p.red as u32,
+            Self::Bitmask { red, green, blue, .. } => red.pack(p.red) | green.pack(p.green) | blue.pack(p.blue),
+        }
+    }
+
+    /// 按屏幕位置 pack,低位深掩码格式会做有序抖动
+    #[inline(always)]
+    pub fn pack_at(&self, p: BltPixel, x: usize, y: usize) -> u32 {
+        match self {
+            Self::Bitmask { red, green, blue, .. } if self.needs_dither() =>
+                red.pack_dithered(p.red, x, y) | green.pack_dithered(p.green, x, y) | blue.pack_dithered(p.blue, x, y),
+            _ => self.pack(p),
+        }
+    }
+
+    /// 写一个已经 pack 好的像素
+    #[inline(always)]
+    pub unsafe fn write_packed(&self, dst: *mut u8, value: u32) {
+        unsafe {
+            match self.bytes_per_pixel() {
+                4 => (dst as *mut u32).write_unaligned(value),
+                2 => (dst as *mut u16).write_unaligned(value as u16),
+                1 => dst.write(value as u8),
+                n => core::ptr::copy_nonoverlapping(value.to_le_bytes().as_ptr(), dst, n),
+            }
+        }
+    }
+
+    /// 把一行 BltPixel 转换并写进显存(或者任意按本布局排列的缓冲区)
+    /// dst 至少要有 src.len() * bytes_per_pixel 字节
+    /// (x, y) 为 dst 第一个像素的屏幕坐标,抖动用
+    #[inline]
+    pub unsafe fn write_row(&self, src: &[BltPixel], dst: *mut u8, x: usize, y: usize) {
+        unsafe {
+            match self {
+                // 布局一致,整行直接拷
+                Self::Bgr | Self::BltOnly =>
+                    core::ptr::copy_nonoverlapping(src.as_ptr() as *const u8, dst, src.len() * 4),
+                _ => self.write_row_map(src, dst, x, y, |p| p),
+            }
+        }
+    }
+
+    /// 同 write_row,但每个像素先过一遍 f,用来把滤镜融合进拷贝
+    #[inline]
+    pub unsafe fn write_row_map(&self, src: &[BltPixel], dst: *mut u8, x: usize, y: usize, f: impl Fn(BltPixel) -> BltPixel) {
+        let bpp = self.bytes_per_pixel();
+        if self.needs_dither() {
+            for (i, p) in src.iter().enumerate() {
+                unsafe { self.write_packed(dst.add(i * bpp), self.pack_at(f(*p), x + i, y)) }
+            }
+        } else {
+            for (i, p) in src.iter().enumerate() {
+                unsafe { self.write_packed(dst.add(i * bpp), self.pack(f(*p))) }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // RGB565,老主板上最常见的 16bit 掩码模式
+    const RGB565: PixelBitmask = PixelBitmask { red: 0xF800, green: 0x07E0, blue: 0x001F, reserved: 0 };
+
+    // 参考图像:纯红 100 抖动到 565 后的 8x8 结果
+    // 100 * 31 / 255 = 12.16,红色 64 格里 10 格进到 13 档(Bayer 阈值 >= 54 的位置),绿蓝为 0
+    const LO: u16 = 12 << 11;
+    const HI: u16 = 13 << 11;
+    const RED100_565: [[u16; 8]; 8] = [
+        [LO, LO, LO, LO, LO, LO, LO, LO],
+        [LO, LO, HI, LO, LO, LO, HI, LO],
+        [LO, LO, LO, LO, LO, LO, LO, LO],
+        [HI, LO, LO, LO, HI, LO, HI, LO],
+        [LO, LO, LO, LO, LO, LO, LO, LO],
+        [LO, LO, HI, LO, LO, LO, HI, LO],
+        [LO, LO, LO, LO, LO, LO, LO, LO],
+        [HI, LO, HI, LO, HI, LO, LO, LO],
+    ];
+
+    // 把 8x8 的同色块按 565 写进缓冲区
+    fn render_block(layout: &PixelLayout, color: BltPixel) -> [[u16; 8]; 8] {
+        let row = [color; 8];
+        let mut out = [[0u16; 8]; 8];
+        for (y, dst) in out.iter_mut().enumerate() {
+            unsafe { layout.write_row(&row, dst.as_mut_ptr() as *mut u8, 0, y) }
+        }
+        out
+    }
+
+    #[test]
+    fn rgb565_needs_dither() {
+        assert!(PixelLayout::from_bitmask(RGB565).needs_dither());
+    }
+
+    // 和参考图像逐像素比对
+    #[test]
+    fn red100_matches_reference() {
+        let layout = PixelLayout::from_bitmask(RGB565);
+        assert_eq!(render_block(&layout, BltPixel::new(100, 0, 0)), RED100_565);
+    }
+
+    // 整条灰阶:抖动后 8x8 块的平均档位还原回 8bit,应当和原值一致(四舍五入误差 1 以内)
+    #[test]
+    fn gray_ramp_average_is_preserved() {
+        let layout = PixelLayout::from_bitmask(RGB565);
+        for v in 0..=255u8 {
+            let block = render_block(&layout, BltPixel::new(v, v, v));
+            let red: u32 = block.iter().flatten().map(|p| (p >> 11) as u32).sum();
+            let green: u32 = block.iter().flatten().map(|p| ((p >> 5) & 0x3F) as u32).sum();
+            let blue: u32 = block.iter().flatten().map(|p| (p & 0x1F) as u32).sum();
+            let red = (red * 255 + 31 * 32) / (31 * 64);
+            let green = (green * 255 + 63 * 32) / (63 * 64);
+            let blue = (blue * 255 + 31 * 32) / (31 * 64);
+            assert!(red.abs_diff(v as u32) <= 1, "red {} -> {}", v, red);
+            assert!(green.abs_diff(v as u32) <= 1, "green {} -> {}", v, green);
+            assert!(blue.abs_diff(v as u32) <= 1, "blue {} -> {}", v, blue);
+        }
+    }
+}
@@ -0,0 +1,61 @@
+/// 面板安装方向,画面按顺时针旋转的角度
+/// 物理坐标是 GOP 模式里的坐标,逻辑坐标是旋转后用户看到的坐标
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rotation {
+    Deg0,
+    Deg90,
+    Deg180,
+    Deg270,
+}
+
+impl Rotation {
+    /// 配置里写角度:0 90 180 270
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "0" => Self::Deg0,
+            "90" => Self::Deg90,
+            "180" => Self::Deg180,
+            "270" => Self::Deg270,
+            _ => return None,
+        })
+    }
+
+    /// 物理分辨率 -> 逻辑分辨率(竖屏时宽高互换,反过来也一样)
+    #[inline]
+    pub fn logical_size(self, (w, h): (usize, usize)) -> (usize, usize) {
+        match self {
+            Self::Deg90 | Self::Deg270 => (h, w),
+            _ => (w, h),
+        }
+    }
+
+    /// 逻辑坐标 -> 物理坐标,phys 为物理分辨率
+    #[inline(always)]
+    pub fn to_physical(self, x: usize, y: usize, (pw, ph): (usize, usize)) -> (usize, usize) {
+        match self {
+            Self::Deg0 => (x, y),
+            Self::Deg90 => (pw - 1 - y, x),
+            Self::Deg180 => (pw - 1 - x, ph - 1 - y),
+            Self::Deg270 => (y, ph - 1 - x),
+        }
+    }
+
+    /// 物理坐标 -> 逻辑坐标
+    #[inline(always)]
+    pub fn to_logical(self, x: usize, y: usize, (pw, ph): (usize, usize)) -> (usize, usize) {
+        match self {
+            Self::Deg0 => (x, y),
+            Self::Deg90 => (y, pw - 1 - x),
+            Self::Deg180 => (pw - 1 - x, ph - 1 - y),
+            Self::Deg270 => (ph - 1 - y, x),
+        }
+    }
+
+    /// 逻辑矩形 (x, y, w, h) -> 物理矩形
+    pub fn rect_to_physical(self, (x, y, w, h): (usize, usize, usize, usize), phys: (usize, usize)) -> (usize, usize, usize, usize) {
+        if w == 0 || h == 0 { return (0, 0, 0, 0) }
+        let (ax, ay) = self.to_physical(x, y, phys);
+        let (bx, by) = self.to_physical(x + w - 1, y + h - 1, phys);
+        (ax.min(bx), ay.min(by), ax.abs_diff(bx) + 1, ay.abs_diff(by) + 1)
+    }
+}
@@ -0,0 +1,321 @@
+use alloc::vec::Vec;
+use uefi::proto::console::gop::BltPixel;
+use crate::graphics::rotate::Rotation;
+
+/// 画面怎么放进屏幕
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScaleMode {
+    /// 原始大小居中,大了就裁
+    None,
+    /// 等比缩放到完整显示,剩下的补边(letterbox/pillarbox)
+    Fit,
+    /// 等比缩放到铺满,多出的裁掉
+    Fill,
+    /// 拉伸铺满,不管比例
+    Stretch,
+    /// 最大整数倍放大后居中,像素风视频用
+    Integer,
+}
+
+impl ScaleMode {
+    /// 配置里的名字,大小写不敏感
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name.to_ascii_lowercase().as_str() {
+            "none" => Self::None,
+            "fit" => Self::Fit,
+            "fill" => Self::Fill,
+            "stretch" => Self::Stretch,
+            "integer" => Self::Integer,
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScaleFilter {
+    Nearest,
+    Bilinear,
+}
+
+impl ScaleFilter {
+    /// 配置里的名字,大小写不敏感
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name.to_ascii_lowercase().as_str() {
+            "nearest" => Self::Nearest,
+            "bilinear" => Self::Bilinear,
+            _ => return None,
+        })
+    }
+}
+
+// 16.16 定点
+const FRAC_BITS: u32 = 16;
+const FRAC_ONE: i64 = 1 << FRAC_BITS;
+
+/// 预先算好的缩放表,所有核心共用,只读
+/// 每个逻辑坐标对应一个源坐标(16.16 定点),超出视频区域的坐标画边框色
+/// 输出按物理行取,旋转在这里一并处理
+pub struct Scaler {
+    src: (usize, usize),
+    phys: (usize, usize),
+    rotation: Rotation,
+    filter: ScaleFilter,
+    border: BltPixel,
+    // 逻辑屏幕上有画面的列/行区间 [start, end)
+    cols: (usize, usize),
+    rows: (usize, usize),
+    x_map: Vec<u32>,
+    y_map: Vec<u32>,
+    // 1:1 且横向铺满屏幕,可以直接借用源行
+    passthrough: bool,
+}
+
+impl Scaler {
+    /// dst 为物理分辨率
+    pub fn new(src: (usize, usize), dst: (usize, usize), mode: ScaleMode, filter: ScaleFilter, border: BltPixel, rotation: Rotation) -> Self {
+        let (sw, sh) = (src.0.max(1), src.1.max(1));
+        let (dw, dh) = rotation.logical_size(dst);
+
+        // 画面在屏幕上的目标尺寸
+        let (rw, rh) = match mode {
+            ScaleMode::None => (sw, sh),
+            ScaleMode::Stretch => (dw, dh),
+            // 比较 dw/sw 和 dh/sh,交叉相乘避免浮点
+            ScaleMode::Fit if dw * sh <= dh * sw => (dw, sh * dw / sw),
+            ScaleMode::Fit => (sw * dh / sh, dh),
+            ScaleMode::Fill if dw * sh >= dh * sw => (dw, sh * dw / sw),
+            ScaleMode::Fill => (sw * dh / sh, dh),
+            ScaleMode::Integer => {
+                let k = (dw / sw).min(dh / sh).max(1);
+                (sw * k, sh * k)
+            }
+        };
+        let (rw, rh) = (rw.max(1), rh.max(1));
+
+        // 居中,可能为负(裁剪)
+        let rx = (dw as isize - rw as isize) / 2;
+        let ry = (dh as isize - rh as isize) / 2;
+
+        let filter = if (rw, rh) == (sw, sh) { ScaleFilter::Nearest } else { filter };
+        let (cols, x_map) = Self::axis(dw, rx, rw, sw, filter);
+        let (rows, y_map) = Self::axis(dh, ry, rh, sh, filter);
+        let passthrough = rotation == Rotation::Deg0 && (rw, rx) == (sw, 0) && sw == dw;
+
+        Self { src: (sw, sh), phys: dst, rotation, filter, border, cols, rows, x_map, y_map, passthrough }
+    }
+
+    // 一个方向上的映射表:屏幕坐标 -> 源坐标(16.16)
+    fn axis(dst_len: usize, offset: isize, len: usize, src_len: usize, filter: ScaleFilter) -> ((usize, usize), Vec<u32>) {
+        let start = offset.clamp(0, dst_len as isize) as usize;
+        let end = (offset + len as isize).clamp(0, dst_len as isize) as usize;
+        let max = ((src_len as i64 - 1) << FRAC_BITS).max(0);
+
+        let map = (0..dst_len).map(|d| {
+            if d < start || d >= end { return 0 }
+            // 按像素中心对齐
+            let local = d as i64 - offset as i64;
+            let center = (((2 * local + 1) * src_len as i64) << FRAC_BITS) / (2 * len as i64);
+            let pos = match filter {
+                ScaleFilter::Nearest => center,
+                // 双线性取左上角的采样点,小数部分当权重
+                ScaleFilter::Bilinear => center - FRAC_ONE / 2,
+            };
+            pos.clamp(0, max) as u32
+        }).collect();
+
+        ((start, end), map)
+    }
+
+    /// 画面原样铺满整个屏幕,不缩放不补边,可以整帧直接拷
+    pub fn is_identity(&self) -> bool {
+        self.passthrough && self.src == self.phys
+    }
+
+    /// 物理屏幕第 y 行的内容,scratch 至少为物理屏幕宽度
+    /// 直通时直接返回源帧里的行,不经过 scratch
+    #[inline]
+    pub fn row<'a>(&self, frame: &'a [BltPixel], y: usize, scratch: &'a mut [BltPixel]) -> &'a [BltPixel] {
+        if self.passthrough && y >= self.rows.0 && y < self.rows.1 {
+            let sw = self.src.0;
+            let sy = (self.y_map[y] >> FRAC_BITS) as usize;
+            return &frame[sy * sw..sy * sw + sw];
+        }
+        let out = &mut scratch[..self.phys.0];
+        self.render_row(frame, y, out);
+        out
+    }
+
+    /// 把物理屏幕第 y 行画进 out,out 长度为物理屏幕宽度
+    pub fn render_row(&self, frame: &[BltPixel], y: usize, out: &mut [BltPixel]) {
+        match self.rotation {
+            Rotation::Deg0 => self.render_logical_row(frame, y, out),
+            Rotation::Deg180 => {
+                self.render_logical_row(frame, self.phys.1 - 1 - y, out);
+                out.reverse();
+            }
+            // 物理行对应逻辑列,只能逐点采样
+            Rotation::Deg90 | Rotation::Deg270 => {
+                for (x, p) in out.iter_mut().enumerate() {
+                    let (lx, ly) = self.rotation.to_logical(x, y, self.phys);
+                    *p = self.sample(frame, lx, ly);
+                }
+            }
+        }
+    }
+
+    // 逻辑行,按行连续取样
+    fn render_logical_row(&self, frame: &[BltPixel], y: usize, out: &mut [BltPixel]) {
+        let (sw, sh) = self.src;
+
+        if y < self.rows.0 || y >= self.rows.1 {
+            out.fill(self.border);
+            return;
+        }
+
+        let fy = self.y_map[y];
+        let sy = (fy >> FRAC_BITS) as usize;
+        let (x0, x1) = self.cols;
+        out[..x0].fill(self.border);
+        out[x1..].fill(self.border);
+
+        match self.filter {
+            ScaleFilter::Nearest => {
+                let src_row = &frame[sy * sw..sy * sw + sw];
+                for (p, &fx) in out[x0..x1].iter_mut().zip(&self.x_map[x0..x1]) {
+                    *p = src_row[(fx >> FRAC_BITS) as usize];
+                }
+            }
+            ScaleFilter::Bilinear => {
+                let sy1 = (sy + 1).min(sh - 1);
+                let wy = (fy >> (FRAC_BITS - 8)) & 0xFF;
+                let top = &frame[sy * sw..sy * sw + sw];
+                let bottom = &frame[sy1 * sw..sy1 * sw + sw];
+                for (p, &fx) in out[x0..x1].iter_mut().zip(&self.x_map[x0..x1]) {
+                    let sx = (fx >> FRAC_BITS) as usize;
+                    let sx1 = (sx + 1).min(sw - 1);
+                    let wx = (fx >> (FRAC_BITS - 8)) & 0xFF;
+                    *p = lerp2(top[sx], top[sx1], bottom[sx], bottom[sx1], wx, wy);
+                }
+            }
+        }
+    }
+
+    // 单点采样,旋转 90/270 时用
+    #[inline(always)]
+    fn sample(&self, frame: &[BltPixel], x: usize, y: usize) -> BltPixel {
+        if x < self.cols.0 || x >= self.cols.1 || y < self.rows.0 || y >= self.rows.1 {
+            return self.border;
+        }
+        let (sw, sh) = self.src;
+        let (fx, fy) = (self.x_map[x], self.y_map[y]);
+        let (sx, sy) = ((fx >> FRAC_BITS) as usize, (fy >> FRAC_BITS) as usize);
+        match self.filter {
+            ScaleFilter::Nearest => frame[sy * sw + sx],
+            ScaleFilter::Bilinear => {
+                let sx1 = (sx + 1).min(sw - 1);
+                let sy1 = (sy + 1).min(sh - 1);
+                let wx = (fx >> (FRAC_BITS - 8)) & 0xFF;
+                let wy = (fy >> (FRAC_BITS - 8)) & 0xFF;
+                lerp2(frame[sy * sw + sx], frame[sy * sw + sx1], frame[sy1 * sw + sx], frame[sy1 * sw + sx1], wx, wy)
+            }
+        }
+    }
+}
+
+// 双线性插值,权重为 0..=255
+#[inline(always)]
+fn lerp2(a: BltPixel, b: BltPixel, c: BltPixel, d: BltPixel, wx: u32, wy: u32) -> BltPixel {
+    #[inline(always)]
+    fn mix(p: u8, q: u8, w: u32) -> u32 { (p as u32 * (256 - w) + q as u32 * w) >> 8 }
+    #[inline(always)]
+    fn mix2(top: u32, bottom: u32, w: u32) -> u8 { ((top * (256 - w) + bottom * w) >> 8) as u8 }
+
+    BltPixel::new(
+        mix2(mix(a.red, b.red, wx), mix(c.red, d.red, wx), wy),
+        mix2(mix(a.green, b.green, wx), mix(c.green, d.green, wx), wy),
+        mix2(mix(a.blue, b.blue, wx), mix(c.blue, d.blue, wx), wy),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCREEN: (usize, usize) = (1024, 768);
+
+    fn scaler(src: (usize, usize), mode: ScaleMode, rotation: Rotation) -> Scaler {
+        Scaler::new(src, SCREEN, mode, ScaleFilter::Nearest, BltPixel::new(0, 0, 0), rotation)
+    }
+
+    // 画面落在逻辑屏幕上的区间:(列, 行)
+    fn rect(src: (usize, usize), mode: ScaleMode) -> ((usize, usize), (usize, usize)) {
+        let s = scaler(src, mode, Rotation::Deg0);
+        (s.cols, s.rows)
+    }
+
+    #[test]
+    fn fit_letterboxes() {
+        // 16:9 放进 4:3,上下补边
+        assert_eq!(rect((640, 360), ScaleMode::Fit), ((0, 1024), (96, 672)));
+        // 竖屏视频,左右补边
+        assert_eq!(rect((360, 640), ScaleMode::Fit), ((296, 728), (0, 768)));
+    }
+
+    #[test]
+    fn fill_covers_screen() {
+        assert_eq!(rect((640, 360), ScaleMode::Fill), ((0, 1024), (0, 768)));
+        // 宽 1365 裁掉两边,中间那列对应源的中间
+        let s = scaler((640, 360), ScaleMode::Fill, Rotation::Deg0);
+        assert_eq!((s.x_map[512] >> FRAC_BITS) as usize, 320);
+    }
+
+    #[test]
+    fn integer_and_stretch() {
+        // 1024/640 只能放 1 倍
+        assert_eq!(rect((640, 360), ScaleMode::Integer), ((192, 832), (204, 564)));
+        // 320x240 放 3 倍,960x720
+        assert_eq!(rect((320, 240), ScaleMode::Integer), ((32, 992), (24, 744)));
+        assert_eq!(rect((640, 360), ScaleMode::Stretch), ((0, 1024), (0, 768)));
+    }
+
+    #[test]
+    fn centered_edges_map_to_source_edges() {
+        let s = scaler((640, 360), ScaleMode::None, Rotation::Deg0);
+        assert_eq!((s.cols, s.rows), ((192, 832), (204, 564)));
+        assert_eq!(s.x_map[192] >> FRAC_BITS, 0);
+        assert_eq!(s.x_map[831] >> FRAC_BITS, 639);
+        assert_eq!(s.y_map[204] >> FRAC_BITS, 0);
+        assert_eq!(s.y_map[563] >> FRAC_BITS, 359);
+
+        // 比屏幕大时居中裁剪,左右各裁 (1280 - 1024) / 2 列
+        let s = scaler((1280, 720), ScaleMode::None, Rotation::Deg0);
+        assert_eq!((s.cols, s.rows), ((0, 1024), (24, 744)));
+        assert_eq!(s.x_map[0] >> FRAC_BITS, 128);
+    }
+
+    #[test]
+    fn passthrough_needs_full_width_at_one_to_one() {
+        let s = scaler((1024, 600), ScaleMode::None, Rotation::Deg0);
+        assert!(s.passthrough);
+        assert!(!s.is_identity());
+        assert!(scaler(SCREEN, ScaleMode::Fit, Rotation::Deg0).is_identity());
+        assert!(!scaler((640, 360), ScaleMode::None, Rotation::Deg0).passthrough);
+        assert!(!scaler(SCREEN, ScaleMode::None, Rotation::Deg180).passthrough);
+
+        // 直通时借用源行,不碰 scratch
+        let frame = vec![BltPixel::new(1, 2, 3); 1024 * 600];
+        let mut scratch = vec![BltPixel::new(0, 0, 0); 1024];
+        let row = s.row(&frame, 100, &mut scratch);
+        assert!(core::ptr::eq(row.as_ptr(), frame[(100 - 84) * 1024..].as_ptr()));
+    }
+
+    #[test]
+    fn rotated_uses_logical_size() {
+        // 转 90 度后逻辑屏幕是 768x1024
+        let s = scaler((640, 360), ScaleMode::Fit, Rotation::Deg90);
+        assert_eq!((s.x_map.len(), s.y_map.len()), (768, 1024));
+        assert_eq!((s.cols, s.rows), ((0, 768), (296, 728)));
+        assert_eq!(s.phys, SCREEN);
+        assert!(!s.passthrough);
+    }
+}
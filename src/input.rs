@@ -0,0 +1,81 @@
+use core::sync::atomic::{AtomicBool, Ordering};
+use uefi::proto::console::text::{Key, ScanCode};
+use uefi::boot;
+use uefi::system::with_stdin;
+
+/// 播放时的按键动作
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    TogglePause,
+    /// 按秒跳转,负数后退
+    SeekSeconds(i32),
+    /// 逐帧,负数后退,会顺便暂停
+    Step(i32),
+    SpeedUp,
+    SpeedDown,
+    Restart,
+    ToggleFilters,
+    /// 播放列表下一项/上一项
+    Next,
+    Prev,
+    Quit,
+}
+
+// 开机动画模式下任意键都算退出
+static ANY_KEY_QUITS: AtomicBool = AtomicBool::new(false);
+
+/// 打开后 poll_action 不再查键位表，任意键都返回 Quit
+pub fn set_any_key_quits(on: bool) {
+    ANY_KEY_QUITS.store(on, Ordering::Relaxed);
+}
+
+/// 非阻塞读一个键,没有按键或者读失败都返回 None
+/// 只能在 BSP 上调用,启动服务不是多核安全的
+pub fn poll_key() -> Option<Key> {
+    with_stdin(|stdin| stdin.read_key().ok().flatten())
+}
+
+/// 阻塞等一个键,菜单之类不需要一直刷新的地方用
+pub fn wait_key() -> Option<Key> {
+    loop {
+        if let Some(key) = poll_key() { return Some(key) }
+        let event = with_stdin(|stdin| stdin.wait_for_key_event())?;
+        boot::wait_for_event(&mut [event]).ok()?;
+    }
+}
+
+/// 键位表
+/// 空格 暂停 | ←/→ ±5 秒 | ↑/↓ ±1 帧 | +/- 变速 | R/Home 重播 | F 滤镜
+/// PgDn/N 下一个 | PgUp/P 上一个 | Esc/Q 退出
+pub fn map_key(key: Key) -> Option<Action> {
+    match key {
+        Key::Special(ScanCode::LEFT) => Some(Action::SeekSeconds(-5)),
+        Key::Special(ScanCode::RIGHT) => Some(Action::SeekSeconds(5)),
+        Key::Special(ScanCode::UP) => Some(Action::Step(-1)),
+        Key::Special(ScanCode::DOWN) => Some(Action::Step(1)),
+        Key::Special(ScanCode::HOME) => Some(Action::Restart),
+        Key::Special(ScanCode::PAGE_DOWN) => Some(Action::Next),
+        Key::Special(ScanCode::PAGE_UP) => Some(Action::Prev),
+        Key::Special(ScanCode::ESCAPE) => Some(Action::Quit),
+        Key::Printable(c) => match char::from(c) {
+            ' ' => Some(Action::TogglePause),
+            '+' | '=' | ']' => Some(Action::SpeedUp),
+            '-' | '_' | '[' => Some(Action::SpeedDown),
+            'r' | 'R' => Some(Action::Restart),
+            'f' | 'F' => Some(Action::ToggleFilters),
+            'n' | 'N' => Some(Action::Next),
+            'p' | 'P' => Some(Action::Prev),
+            'q' | 'Q' => Some(Action::Quit),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// 读键并翻译成动作
+#[inline]
+pub fn poll_action() -> Option<Action> {
+    let key = poll_key()?;
+    if ANY_KEY_QUITS.load(Ordering::Relaxed) { return Some(Action::Quit) }
+    map_key(key)
+}
@@ -0,0 +1,289 @@
+use alloc::format;
+use alloc::vec::Vec;
+use core::cell::UnsafeCell;
+use core::fmt::{self, Write};
+use core::sync::atomic::{fence, AtomicBool, AtomicU32, AtomicUsize, Ordering};
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use raw_cpuid::CpuId;
+use uefi::boot::{self, get_image_file_system, image_handle, OpenProtocolAttributes, OpenProtocolParams, ScopedProtocol};
+use uefi::proto::console::serial::Serial;
+use uefi::proto::media::file::{File, FileAttribute, FileMode, RegularFile};
+use uefi::{cstr16, CStr16};
+use crate::clock;
+use crate::config::Config;
+use crate::error::Result;
+
+/// 日志文件,和配置文件放在一起,只往后追加
+pub const LOG_PATH: &CStr16 = cstr16!("\\uefi-player.log");
+
+// 环里留多少条,来不及取的老日志会被覆盖
+const SLOTS: usize = 256;
+// 一条最多多少字节,长的截掉
+const TEXT_LEN: usize = 160;
+
+/// 环里的一条日志
+#[derive(Clone, Copy)]
+pub struct Entry {
+    /// 第几条,从 0 开始一直往上加
+    pub seq: usize,
+    pub level: Level,
+    pub time_ns: u64,
+    len: usize,
+    text: [u8; TEXT_LEN],
+}
+
+impl Entry {
+    const EMPTY: Self = Self { seq: 0, level: Level::Info, time_ns: 0, len: 0, text: [0; TEXT_LEN] };
+
+    pub fn text(&self) -> &str {
+        // 写的时候只在字符边界截断
+        core::str::from_utf8(&self.text[..self.len]).unwrap_or("")
+    }
+}
+
+// 槽位的 stamp:写第 n 条时为 2n+1,写完为 2n+2,读的前后各看一次,变了就是读的时候被覆盖了
+struct Slot {
+    stamp: AtomicUsize,
+    entry: UnsafeCell<Entry>,
+}
+
+// 多个核同时写,只有 BSP 读
+struct Ring {
+    head: AtomicUsize,
+    slots: [Slot; SLOTS],
+}
+
+// 槽位靠 stamp 协调,同一个槽只有领到号的核在写
+unsafe impl Sync for Ring {}
+
+static RING: Ring = Ring {
+    head: AtomicUsize::new(0),
+    slots: [const { Slot { stamp: AtomicUsize::new(0), entry: UnsafeCell::new(Entry::EMPTY) } }; SLOTS],
+};
+
+// 串口和文件已经取到第几条,只在 BSP 上动
+static TAIL: AtomicUsize = AtomicUsize::new(0);
+// 正在往外写,写的过程中又打日志不要重入
+static FLUSHING: AtomicBool = AtomicBool::new(false);
+// 被覆盖掉没来得及写出去的条数
+static DROPPED: AtomicUsize = AtomicUsize::new(0);
+// 只有 BSP 能用启动服务写串口和文件,靠 APIC ID 认
+static BSP_APIC: AtomicU32 = AtomicU32::new(u32::MAX);
+
+// 三个去处各自的级别,存 LevelFilter as usize
+static CONSOLE: AtomicUsize = AtomicUsize::new(LevelFilter::Warn as usize);
+static SERIAL: AtomicUsize = AtomicUsize::new(LevelFilter::Info as usize);
+static FILE: AtomicUsize = AtomicUsize::new(LevelFilter::Off as usize);
+
+struct Logger;
+
+static LOGGER: Logger = Logger;
+
+/// 装上 logger,要在 BSP 上、启动 AP 之前调用
+/// 读到配置之前按默认级别收,先攒在环里
+pub fn init() {
+    BSP_APIC.store(apic_id(), Ordering::Relaxed);
+    if log::set_logger(&LOGGER).is_ok() { update_max_level() }
+}
+
+/// 按配置设各个去处的级别,然后把之前攒下的写出去
+pub fn configure(config: &Config) {
+    CONSOLE.store(config.log_console as usize, Ordering::Relaxed);
+    SERIAL.store(config.log_serial as usize, Ordering::Relaxed);
+    FILE.store(config.log_file as usize, Ordering::Relaxed);
+    update_max_level();
+    flush();
+}
+
+/// 叠加层显示的级别
+pub fn console_level() -> LevelFilter { level(&CONSOLE) }
+
+/// 到现在一共打了多少条
+pub fn head() -> usize { RING.head.load(Ordering::Acquire) }
+
+/// 最近 n 条够得上叠加层级别的日志,老的在前
+pub fn recent(n: usize) -> Vec<Entry> {
+    let console = console_level();
+    let head = RING.head.load(Ordering::Acquire);
+    let mut entries: Vec<Entry> = (head.saturating_sub(SLOTS)..head).rev()
+        .filter_map(|seq| read(seq).ok())
+        .filter(|e| e.level <= console)
+        .take(n)
+        .collect();
+    entries.reverse();
+    entries
+}
+
+/// 把环里还没写出去的日志写到串口和文件,只在 BSP 上有效,AP 上调用直接返回
+/// 打日志时 BSP 上会自动调用,播放时 BSP 每轮也调用一次把 AP 的日志取走
+pub fn flush() {
+    if !on_bsp() || FLUSHING.swap(true, Ordering::Acquire) { return }
+
+    let head = RING.head.load(Ordering::Acquire);
+    let mut tail = TAIL.load(Ordering::Relaxed);
+    if head - tail > SLOTS {
+        DROPPED.fetch_add(head - tail - SLOTS, Ordering::Relaxed);
+        tail = head - SLOTS;
+    }
+
+    let (serial_level, file_level) = (level(&SERIAL), level(&FILE));
+    let mut sinks = Sinks::default();
+    while tail < head {
+        match read(tail) {
+            Ok(entry) => {
+                let line = format!("[{:>5}.{:06}] {:<5} {}", entry.time_ns / 1_000_000_000, entry.time_ns / 1000 % 1_000_000, entry.level, entry.text());
+                if entry.level <= serial_level { sinks.serial(&line) }
+                if entry.level <= file_level { sinks.file(&line) }
+            }
+            Err(Missing::Overwritten) => { DROPPED.fetch_add(1, Ordering::Relaxed); }
+            // 还有核在写这条,下次再来
+            Err(Missing::Pending) => break,
+        }
+        tail += 1;
+    }
+    TAIL.store(tail, Ordering::Relaxed);
+    FLUSHING.store(false, Ordering::Release);
+
+    // 这两条自己也是日志,下一次 flush 写出去
+    let lost = DROPPED.swap(0, Ordering::Relaxed);
+    if lost > 0 { log::warn!("{} log messages were overwritten before they could be written", lost) }
+    // 文件打不开就不再试了,换到串口上报一声
+    if sinks.file_failed {
+        FILE.store(LevelFilter::Off as usize, Ordering::Relaxed);
+        update_max_level();
+        log::warn!("log file {} unavailable, file logging disabled", LOG_PATH);
+    }
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    // AP 上也会走到这里,不能分配内存也不能用启动服务,只写环
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) { return }
+        push(record.level(), *record.args());
+        flush();
+    }
+
+    fn flush(&self) { flush() }
+}
+
+fn push(level: Level, args: fmt::Arguments) {
+    let seq = RING.head.fetch_add(1, Ordering::AcqRel);
+    let slot = &RING.slots[seq % SLOTS];
+    slot.stamp.store(2 * seq + 1, Ordering::Relaxed);
+    fence(Ordering::Release);
+
+    let entry = unsafe { &mut *slot.entry.get() };
+    entry.seq = seq;
+    entry.level = level;
+    entry.time_ns = if clock::ready() { clock::now_ns() } else { 0 };
+    let mut text = Text { buf: &mut entry.text, len: 0 };
+    let _ = text.write_fmt(args);
+    entry.len = text.len;
+
+    slot.stamp.store(2 * seq + 2, Ordering::Release);
+}
+
+enum Missing {
+    // 已经被后来的覆盖了
+    Overwritten,
+    // 领了号还没写完
+    Pending,
+}
+
+fn read(seq: usize) -> core::result::Result<Entry, Missing> {
+    let slot = &RING.slots[seq % SLOTS];
+    let stamp = slot.stamp.load(Ordering::Acquire);
+    if stamp > 2 * seq + 2 { return Err(Missing::Overwritten) }
+    if stamp != 2 * seq + 2 { return Err(Missing::Pending) }
+    let entry = unsafe { core::ptr::read_volatile(slot.entry.get()) };
+    fence(Ordering::Acquire);
+    if slot.stamp.load(Ordering::Relaxed) != stamp { return Err(Missing::Overwritten) }
+    Ok(entry)
+}
+
+// 写满就截断,不在 UTF-8 字符中间断开
+struct Text<'a> {
+    buf: &'a mut [u8; TEXT_LEN],
+    len: usize,
+}
+
+impl Write for Text<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let room = TEXT_LEN - self.len;
+        let mut n = s.len().min(room);
+        while !s.is_char_boundary(n) { n -= 1 }
+        self.buf[self.len..self.len + n].copy_from_slice(&s.as_bytes()[..n]);
+        self.len += n;
+        Ok(())
+    }
+}
+
+// 一次 flush 里用到才打开,结束时关掉
+#[derive(Default)]
+struct Sinks {
+    serial: Option<Option<ScopedProtocol<Serial>>>,
+    file: Option<RegularFile>,
+    file_failed: bool,
+}
+
+impl Sinks {
+    fn serial(&mut self, line: &str) {
+        let serial = self.serial.get_or_insert_with(|| {
+            let handle = boot::get_handle_for_protocol::<Serial>().ok()?;
+            // 只借用不独占,固件自己的串口控制台还要用
+            let params = OpenProtocolParams { handle, agent: image_handle(), controller: None };
+            unsafe { boot::open_protocol::<Serial>(params, OpenProtocolAttributes::GetProtocol) }.ok()
+        });
+        if let Some(serial) = serial {
+            let _ = serial.write(line.as_bytes());
+            let _ = serial.write(b"\r\n");
+        }
+    }
+
+    fn file(&mut self, line: &str) {
+        if self.file_failed { return }
+        if self.file.is_none() {
+            match open_log_file() {
+                Ok(file) => self.file = Some(file),
+                Err(_) => {
+                    self.file_failed = true;
+                    return;
+                }
+            }
+        }
+        if let Some(file) = &mut self.file {
+            let ok = file.write(line.as_bytes()).is_ok() && file.write(b"\n").is_ok();
+            self.file_failed = !ok;
+        }
+    }
+}
+
+fn open_log_file() -> Result<RegularFile> {
+    let mut root = get_image_file_system(image_handle())?.open_volume()?;
+    let mut file = root.open(LOG_PATH, FileMode::CreateReadWrite, FileAttribute::empty())?
+        .into_regular_file()
+        .ok_or(crate::error::NyaStatus::NotRegularFile)?;
+    file.set_position(RegularFile::END_OF_FILE)?;
+    Ok(file)
+}
+
+fn level(sink: &AtomicUsize) -> LevelFilter {
+    LevelFilter::iter().nth(sink.load(Ordering::Relaxed)).unwrap_or(LevelFilter::Off)
+}
+
+// 三个去处里最宽的那个,更低的级别在 log 宏里就直接丢掉
+fn update_max_level() {
+    log::set_max_level(level(&CONSOLE).max(level(&SERIAL)).max(level(&FILE)));
+}
+
+fn apic_id() -> u32 {
+    CpuId::new().get_feature_info().map_or(0, |info| info.initial_local_apic_id() as u32)
+}
+
+fn on_bsp() -> bool {
+    apic_id() == BSP_APIC.load(Ordering::Relaxed)
+}
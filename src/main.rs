@@ -1,28 +1,84 @@
-#![no_main]
-#![no_std]
+#![cfg_attr(not(test), no_main)]
+#![cfg_attr(not(test), no_std)]
 
 extern crate alloc;
 
+mod args;
+mod browser;
+mod chain;
+mod clock;
+mod config;
 mod fs;
 mod graphics;
 mod error;
+mod input;
+mod logger;
+mod net;
 mod video;
 mod test;
 
 use uefi::prelude::*;
-use core::time::Duration;
+use crate::chain::Target;
+use crate::config::Config;
 use crate::error::handle_fatal;
-use crate::graphics::Screen;
+use crate::fs::Fs;
+use crate::graphics::{font, Screen};
 use crate::video::video_run;
 
 #[entry]
 fn main() -> Status {
     uefi::helpers::init().expect("Failed to init UEFI");
-
+    // 日志先攒在环里,读到配置再决定往哪写
+    logger::init();
+    // 启动 AP 之前校准好，之后所有核心共用
+    clock::init();
 
     let mut screen = Screen::new().expect("Failed to create screen");
-    video_run(&mut screen).unwrap_or_else(|e| handle_fatal(e, &mut screen));
+    // 配置写错了把行号显示出来，然后交回固件
+    // 命令行优先于配置文件
+    // 字体跟着配置一起读,读坏了也一样报错
+    let config = Fs::new().and_then(|mut fs| {
+        let mut config = Config::load(&mut fs)?;
+        args::apply(&mut config, &args::args())?;
+        let (w, h) = screen.resolution();
+        font::configure(&mut fs, &config, w.min(h))?;
+        Ok(config)
+    });
+    let config = match config {
+        Ok(config) => config,
+        Err(e) => {
+            let status = handle_fatal(e, &mut screen);
+            clock::restore();
+            return status;
+        }
+    };
+    logger::configure(&config);
+    screen.set_rotation(config.rotation);
+    let chain = config.chain.as_deref().map(Target::parse);
+    let status = match video_run(&mut screen, &config) {
+        Ok(()) => Status::SUCCESS,
+        // 开机动画出错也要接着启动，不能停在错误页上
+        Err(e) if chain.is_some() => {
+            log::error!("splash failed: {:?}", e);
+            e.status()
+        }
+        Err(e) => handle_fatal(e, &mut screen),
+    };
+
+    // 还原显示模式并释放 GOP，再重置文字控制台，交回固件时屏幕是干净的
+    drop(screen);
+    uefi::system::with_stdout(|out| { let _ = out.reset(false); });
+    clock::restore();
 
-    boot::stall(Duration::from_mins(2));
-    Status::SUCCESS
+    // 启动失败就返回固件，由启动管理器接着试下一项
+    if let Some(target) = chain {
+        return match chain::start(&target) {
+            Ok(status) => status,
+            Err(e) => {
+                log::warn!("chainload {:?} failed: {:?}, returning to boot manager", target, e);
+                e.status()
+            }
+        };
+    }
+    status
 }
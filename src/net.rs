@@ -0,0 +1,84 @@
+use alloc::vec;
+use alloc::vec::Vec;
+use core::net::{IpAddr, Ipv4Addr};
+use uefi::boot::{self, OpenProtocolAttributes, OpenProtocolParams};
+use uefi::proto::network::pxe::{BaseCode, DhcpV4Packet};
+use uefi::CStr8;
+use crate::error::{NyaStatus, Result};
+use crate::fs::Reader;
+
+const SCHEME: &str = "tftp:";
+
+/// tftp: 开头的路径,大小写不敏感
+pub fn is_tftp(path: &str) -> bool {
+    path.get(..SCHEME.len()).is_some_and(|s| s.eq_ignore_ascii_case(SCHEME))
+}
+
+/// TFTP 上的文件,打开时整个拉进内存,之后按块读
+/// PXE Base Code 的 TFTP 读只能一次读完整个文件,没法按偏移续读
+///
+/// 本地测试用 QEMU 自带的 TFTP 服务器,不需要外网:
+/// `-netdev user,id=net0,tftp=./media -device virtio-net-pci,netdev=net0`
+/// 然后 source = tftp:/video.qois,服务器地址用 DHCP 给的 (QEMU 里是 10.0.2.2)
+pub struct Tftp {
+    data: Vec<u8>,
+    pos: usize,
+}
+
+impl Tftp {
+    /// url 为 tftp://服务器IP/路径,或者 tftp:/路径 用 DHCP 给的服务器
+    pub fn open(url: &str) -> Result<Self> {
+        let rest = &url[SCHEME.len()..];
+        let (server, path) = match rest.strip_prefix("//") {
+            Some(rest) => {
+                let (host, path) = rest.split_once('/').unwrap_or((rest, ""));
+                let ip = host.parse::<Ipv4Addr>()
+                    .map_err(|_| NyaStatus::_Debug(alloc::format!("{}: bad server address '{}'", url, host)))?;
+                (Some(ip), path)
+            }
+            None => (None, rest.trim_start_matches('/')),
+        };
+
+        let handle = boot::get_handle_for_protocol::<BaseCode>()?;
+        // 只借用不独占,独占会把固件的网络栈从这个网卡上断开,本程序可能就是从这里加载的
+        let params = OpenProtocolParams { handle, agent: boot::image_handle(), controller: None };
+        let mut pxe = unsafe { boot::open_protocol::<BaseCode>(params, OpenProtocolAttributes::GetProtocol) }?;
+        // 从网络启动时固件已经启动过并拿到了地址
+        if !pxe.mode().started() { pxe.start(false)? }
+        if !pxe.mode().dhcp_ack_received() { pxe.dhcp(true)? }
+        let server = server.unwrap_or_else(|| {
+            let ack: &DhcpV4Packet = pxe.mode().dhcp_ack().as_ref();
+            Ipv4Addr::from(ack.bootp_si_addr)
+        });
+        let server = IpAddr::V4(server);
+
+        let mut name = Vec::from(path.as_bytes());
+        name.push(0);
+        let name = CStr8::from_bytes_with_nul(&name).map_err(|_| NyaStatus::FromStrWithBufError)?;
+        let size = pxe.tftp_get_file_size(&server, name)?;
+        let mut data = vec![0u8; size as usize];
+        let read = pxe.tftp_read_file(&server, name, Some(&mut data))?;
+        data.truncate(read as usize);
+
+        log::info!("tftp: {} bytes of {} from {}", data.len(), path, server);
+        Ok(Self { data, pos: 0 })
+    }
+}
+
+impl Reader for Tftp {
+    fn read_chunk(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let n = buf.len().min(self.data.len() - self.pos);
+        buf[..n].copy_from_slice(&self.data[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+
+    fn rewind(&mut self) -> Result {
+        self.pos = 0;
+        Ok(())
+    }
+
+    fn size(&mut self) -> Result<u64> {
+        Ok(self.data.len() as u64)
+    }
+}
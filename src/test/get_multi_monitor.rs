@@ -4,71 +4,25 @@ use alloc::vec::Vec;
 use core::time::Duration;
 use uefi::boot::{find_handles, get_handle_for_protocol, open_protocol_exclusive, ScopedProtocol};
 use uefi::{boot, println};
-use uefi::proto::console::gop::{BltOp, BltPixel, GraphicsOutput, ModeInfo};
-use crate::video::ascii_font::FONT_8X16;
+use uefi::proto::console::gop::{GraphicsOutput, ModeInfo};
+use crate::graphics::console::Console;
+use crate::graphics::rotate::Rotation;
 
 fn info2str(a: &str, info: ModeInfo) -> String {
     let r = format!("resolution:{:?}", info.resolution());
     let f = format!("pixel_format:{:?}", info.pixel_format());
     let s = format!("stride:{:?}", info.stride());
     let b = format!("pixel_bitmask:{:?}", info.pixel_bitmask());
-    format!("[{}]{} {} {} {}", a, r, f, s, b)
+    format!("\x1b[96m[{}]\x1b[0m{} {} {} {}", a, r, f, s, b)
 }
 
-static mut OFFSET: usize = 0;
-fn println(gop: &mut ScopedProtocol<GraphicsOutput>, text: &str) {
-    let mut x = 0;
-    // 获取当前屏幕的宽度，用于自动换行
-    let (width, height) = gop.current_mode_info().resolution();
-
-    let fg = BltPixel::new(255, 255, 255);
-    let bg = BltPixel::new(0, 0, 0);
-
-    // 超出屏幕高度自动归零
-    unsafe { if OFFSET + 18 >= height { OFFSET = 0 } }
-
-    for c in text.chars() {
-        if c == '\n' {
-            x = 0;
-            unsafe { OFFSET += 16; }
-            continue;
-        }
-
-        // 超出屏幕宽度自动换行 (8 是字符宽度)
-        if x + 8 > width {
-            x = 0;
-            unsafe { OFFSET += 16; }
-        }
-
-        // --- 2. 绘制字符 ---
-
-        let index = (c as usize) & 0x7F;
-        let glyph = &FONT_8X16[index];
-
-        for row in 0..16 {
-            let row_bits = glyph[row];
-            for col in 0..8 {
-                let is_fg = (row_bits >> (7 - col)) & 1 == 1;
-                let color = if is_fg { fg } else { bg };
-
-                // 绘制像素
-                let _ = gop.blt(BltOp::VideoFill {
-                    color,
-                    dest: (x + col, unsafe { OFFSET + row }),
-                    dims: (1, 1),
-                });
-            }
-        }
-
-        // 字符绘制完后，坐标右移
-        x += 8;
-    }
-
-    // 整个字符串画完后，最后额外换一行，防止下一次打印重叠
-    unsafe { OFFSET += 18 }
+// 几个测试传同一个 console 就能接着往下打印,满屏以后上滚
+fn println(console: &mut Console, gop: &mut ScopedProtocol<GraphicsOutput>, text: &str) {
+    let _ = console.write(gop, Rotation::Deg0, text);
+    let _ = console.write(gop, Rotation::Deg0, "\n");
 }
 
-pub fn get_multi_mode() {
+pub fn get_multi_mode(console: &mut Console) {
     let gop = get_handle_for_protocol::<GraphicsOutput>().unwrap();
     let mut gop = open_protocol_exclusive::<GraphicsOutput>(gop).unwrap();
 
@@ -79,17 +33,17 @@ pub fn get_multi_mode() {
         .collect();
 
     for s in mode_strings {
-        println(&mut gop, &s);
+        println(console, &mut gop, &s);
     }
 
     // 2. 获取当前模式
     let info = gop.current_mode_info();
-    println(&mut gop, &info2str("current mode", info))
+    println(console, &mut gop, &info2str("current mode", info))
 }
 
 // 经过测试发现大部分GOP驱动都只支持给我一个Handle(也就是一个显示器)
 // 那多显示器已经毫无意义，已经回滚到单显示器
-pub fn get_multi_monitor() {
+pub fn get_multi_monitor(console: &mut Console) {
     let handle = find_handles::<GraphicsOutput>().expect("Failed to find handles");
 
     // 可以用ok().map() 但是可读性不是很好
@@ -115,7 +69,7 @@ pub fn get_multi_monitor() {
         let mut gop = open_protocol_exclusive::<GraphicsOutput>(*h).unwrap();
         for mode_strings in info {
             for s in mode_strings {
-                println(&mut gop, &s);
+                println(console, &mut gop, &s);
             }
         }
     }
@@ -126,12 +80,12 @@ pub fn get_multi_monitor() {
     //         .map(|mode| info2str("query mode", *mode.info()))
     //         .collect();
     //     for s in mode_strings {
-    //         println(&mut gop, &s);
+    //         println(console, &mut gop, &s);
     //     }
     // }
 }
 
-pub fn test_reopen_protocol() { // it work! : )
+pub fn test_reopen_protocol(console: &mut Console) { // it work! : )
     {
         let gop = get_handle_for_protocol::<GraphicsOutput>().unwrap();
         let gop = open_protocol_exclusive::<GraphicsOutput>(gop).unwrap();
@@ -141,17 +95,17 @@ pub fn test_reopen_protocol() { // it work! : )
     {
         let gop = get_handle_for_protocol::<GraphicsOutput>().unwrap();
         let mut gop = open_protocol_exclusive::<GraphicsOutput>(gop).unwrap();
-        println(&mut gop, "reopen protocol");
+        println(console, &mut gop, "reopen protocol");
     }
 
     {
         let gop = get_handle_for_protocol::<GraphicsOutput>().unwrap();
         let mut gop = open_protocol_exclusive::<GraphicsOutput>(gop).unwrap();
-        println(&mut gop, "reopen protocol again");
+        println(console, &mut gop, "reopen protocol again");
     }
 }
 
-pub fn test_reopen_multi_protocol() { // it dont work :(
+pub fn test_reopen_multi_protocol(console: &mut Console) { // it dont work :(
     {
         let handle = find_handles::<GraphicsOutput>().unwrap();
         for h in handle {
@@ -164,7 +118,7 @@ pub fn test_reopen_multi_protocol() { // it dont work :(
         let handle = find_handles::<GraphicsOutput>().unwrap();
         if let Some(h) = handle.first() {
             let mut gop = open_protocol_exclusive::<GraphicsOutput>(*h).unwrap();
-            println(&mut gop, "reopen protocol");
+            println(console, &mut gop, "reopen protocol");
         }
     }
 }
@@ -5,4 +5,4 @@ pub mod use_embedded_graphics_gop;
 pub mod get_smbios;
 pub mod get_multi_monitor;
 pub mod multi_core_draw;
-pub mod mp_draw;
\ No newline at end of file
+pub mod mp_draw;
@@ -7,52 +7,221 @@ use core::time::Duration;
 use uefi::boot::{create_event, get_handle_for_protocol, open_protocol_exclusive, set_watchdog_timer, EventType, Tpl};
 use uefi::{boot, cstr16, println, CStr16, Status};
 use uefi::proto::console::gop::{BltPixel, GraphicsOutput};
-use uefi::proto::media::file::{File, FileInfo, RegularFile};
 use uefi::proto::pi::mp::MpServices;
-use crate::fs::Fs;
-use crate::graphics::Screen;
+use crate::browser::Browser;
+use crate::clock;
+use crate::config::Config;
+use crate::fs::{Fs, Reader};
+use crate::graphics::{font, Screen};
+use crate::graphics::filter::FilterChain;
+use crate::graphics::overlay::{Compositor, PlaneId};
+use crate::graphics::pixel::PixelLayout;
+use crate::graphics::rotate::Rotation;
+use crate::graphics::scale::Scaler;
+use crate::input::{self, poll_action, Action};
+use crate::logger;
+use crate::video::control::{Control, FrameSlots, Outcome, STOP};
+use crate::video::looping::{AtEnd, LoopMode, LoopState};
+use crate::video::playlist::Playlist;
+use crate::video::logview::LogView;
+use crate::video::osd::Osd;
+use crate::video::pacing::Pacer;
 use crate::video::buffer::{BltFrameBuffer, QoiFrameBuffer, RawFrameBuffer};
-use crate::video::decoder::{VideoMemory, VideoMemoryRaw};
+use crate::video::decoder::{probe_resolution, VideoMemory, VideoMemoryRaw};
 use crate::error::{handle_fatal, NyaStatus, Result};
-use crate::video::ascii_font::FONT_8X16;
 
 pub mod buffer;
 pub mod decoder;
 pub mod ascii_font;
+pub mod control;
+pub mod logview;
+pub mod looping;
+pub mod osd;
+pub mod pacing;
+pub mod playlist;
+
+/// 画法,配置里按名字选
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Renderer {
+    /// 预解码,所有核心分行带画,有 OSD、变速和叠加层
+    Multicore,
+    /// 边读文件边解码,内存占用最小
+    Stream,
+    /// 压缩数据全读进内存,每帧现解码
+    Memory,
+    /// 预解码,单核直接拷进显存
+    Direct,
+}
+
+impl Renderer {
+    /// 配置里的名字,大小写不敏感
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name.to_ascii_lowercase().as_str() {
+            "multicore" | "mp" => Self::Multicore,
+            "stream" => Self::Stream,
+            "memory" => Self::Memory,
+            "direct" => Self::Direct,
+            _ => return None,
+        })
+    }
+}
 
 
-pub fn video_run(screen: &mut Screen) -> Result {
+pub fn video_run(screen: &mut Screen, config: &Config) -> Result {
 
     // 关闭看门狗，如果不行之后写定时喂狗
     set_watchdog_timer(0, 0, None)?;
 
-    const WIDTH: usize = 1920;
-    const HEIGHT: usize = 1080;
-    const SIZE: usize = WIDTH * HEIGHT;
-
     let mut fs = Fs::new()?;
-    let mut file = fs.open_file(cstr16!("1080p\\video.qois"))?;
-    // let mut qoi = QoiFrameBuffer::new(SIZE);
-    // let mut raw = RawFrameBuffer::new(SIZE);
-    // let mut blt = BltFrameBuffer::new(SIZE);
-    // let mut video = VideoMemory::new(file)?;
-    // let mut video_raw = VideoMemoryRaw::new(file)?;
-    // screen.parallel_video_draw_ultra(&mut video_raw, WIDTH, HEIGHT)?;
-    // screen.draw_u64_optimized_loop(&mut video_raw, WIDTH, HEIGHT); // SUPER UNSAFE!!
-    mp_draw(screen, &mut file, WIDTH, HEIGHT)?;
-
-    // loop {
-    //     draw(&mut fs, &mut file, screen, &mut qoi, &mut raw, &mut blt)?;
-    //     draw_all_mem(&mut video, screen, &mut qoi, &mut raw, &mut blt)?;
-    //     draw_all_mem_zero_copy(&mut video, screen, &mut qoi, &mut blt)?;  // UNSAFE!!
-    //     screen.draw_all_mem_raw_zero_copy(&mut video_raw, WIDTH, HEIGHT); // UNSAFE!!
-    //     screen.draw_fast_direct_copy(&mut video_raw, WIDTH, HEIGHT);      // UNSAFE!!
-    //     boot::stall(Duration::from_millis(1000 / 90));
-    // }
+
+    // 开机动画模式：只播一遍，任意键跳过，超时也跳过
+    let splash = config.chain.is_some();
+    input::set_any_key_quits(splash);
+    let deadline = (splash && config.splash_timeout > 0)
+        .then(|| clock::now_ns() + config.splash_timeout as u64 * 1_000_000_000);
+
+    if !config.browse || splash {
+        return play(screen, &mut fs, config, &config.source, deadline);
+    }
+
+    // 浏览模式：播完或者按 Esc 都回到列表，播放出错显示在底栏
+    let mut browser = Browser::new(&mut fs, "")?;
+    while let Some(path) = browser.run(&mut fs, screen)? {
+        browser.status.clear();
+        if let Err(e) = play(screen, &mut fs, config, &path, None) {
+            browser.status = format!("{}: {:?}", path, e);
+        }
+        screen.restore_mode()?;
+    }
+    Ok(())
+}
+
+/// 播放一个视频、目录或播放列表，直到播完或者按了退出
+fn play(screen: &mut Screen, fs: &mut Fs, config: &Config, source: &str, deadline: Option<u64>) -> Result {
+    let mut playlist = Playlist::load(fs, source)?;
+    let splash = config.chain.is_some();
+
+    while let Some(entry) = playlist.current() {
+        let mut file = fs.open_reader(&entry.path)?;
+
+        // 分辨率以视频为准，顺便切换到最合适的显示模式
+        let (width, height) = probe_resolution(&mut *file)?;
+        screen.select_mode((width, height), config.mode)?;
+
+        // 缩放表和滤镜按切换后的模式建一次，几种画法共用
+        // 列表项可以单独指定缩放方式
+        let scaler = Scaler::new(
+            (width, height),
+            screen.get_gop().current_mode_info().resolution(),
+            entry.scale.unwrap_or(config.scale),
+            config.filter,
+            BltPixel::from(config.border),
+            screen.rotation(),
+        );
+        let filters = FilterChain::new(&config.filters, config.filters_enabled);
+
+        let looping = if splash { LoopMode::Once } else { entry.looping.unwrap_or(config.loop_mode) };
+        let outcome = match config.renderer {
+            Renderer::Multicore => mp_draw(screen, &mut *file, width, height, scaler, filters, config, entry.title(), looping, deadline)?,
+            renderer => {
+                let mut out = Output { scaler: &scaler, filters: &filters, size: (width, height), canvas: Vec::new() };
+                single_draw(screen, fs, file, &mut out, config, renderer, looping, deadline)?
+            }
+        };
+        match outcome {
+            Outcome::Finished | Outcome::Next => playlist.next(),
+            Outcome::Prev => playlist.prev(),
+            Outcome::Quit => break,
+        }
+    }
 
     Ok(())
 }
 
+// 单核画法上屏用的东西，和多核一样缩放、补边、加滤镜
+struct Output<'a> {
+    scaler: &'a Scaler,
+    filters: &'a FilterChain,
+    // 视频分辨率，缩放表按它建的
+    size: (usize, usize),
+    canvas: Vec<BltPixel>,
+}
+
+impl Output<'_> {
+    // 和开头探测的分辨率不一样的坏帧对不上缩放表，丢掉
+    fn fits(&self, (width, height): (u32, u32)) -> bool {
+        (width as usize, height as usize) == self.size
+    }
+
+    fn present(&mut self, screen: &mut Screen, frame: &[BltPixel]) -> Result {
+        let len = self.size.0 * self.size.1;
+        if frame.len() < len { return Ok(()) }
+        screen.present_scaled(&frame[..len], self.scaler, self.filters, None, &mut self.canvas)
+    }
+}
+
+// 单核画法各自的数据源
+enum Single {
+    Stream(Box<dyn Reader>, LoopState),
+    Memory(VideoMemory),
+    Direct(VideoMemoryRaw),
+}
+
+/// 单核的几种画法,没有 OSD 和变速,按键只认滤镜开关、切换和退出
+fn single_draw(
+    screen: &mut Screen,
+    fs: &mut Fs,
+    mut file: Box<dyn Reader>,
+    out: &mut Output,
+    config: &Config,
+    renderer: Renderer,
+    looping: LoopMode,
+    deadline: Option<u64>,
+) -> Result<Outcome> {
+    let (width, height) = out.size;
+    let size = width * height;
+    let mut qoi = QoiFrameBuffer::new(size * 4);
+    let mut raw = RawFrameBuffer::new(size * 4);
+    let mut blt = BltFrameBuffer::new(size);
+    let mut source = match renderer {
+        Renderer::Memory => Single::Memory(VideoMemory::new(&mut *file)?),
+        Renderer::Direct => Single::Direct(VideoMemoryRaw::new(&mut *file)?),
+        _ => Single::Stream(file, LoopState::new(looping)),
+    };
+    match &mut source {
+        Single::Memory(v) => v.looping = LoopState::new(looping),
+        Single::Direct(v) => v.looping = LoopState::new(looping),
+        Single::Stream(..) => {}
+    }
+    let mut pacer = Pacer::new(config.fps, screen.refresh_rate());
+
+    loop {
+        let more = match &mut source {
+            Single::Stream(file, state) => draw(fs, &mut **file, screen, out, &mut qoi, &mut raw, &mut blt, state)?,
+            Single::Memory(v) => draw_all_mem(v, screen, out, &mut qoi, &mut raw, &mut blt)?,
+            // 原样铺满屏幕时才能整块拷，否则和其他画法一样走缩放
+            Single::Direct(v) if out.scaler.is_identity() && !out.filters.active() => screen.draw_fast_direct_copy(v, width, height),
+            Single::Direct(v) => match v.next_frame() {
+                Some(frame) => { out.present(screen, frame)?; true }
+                None => false,
+            },
+        };
+        if !more { return Ok(Outcome::Finished) }
+
+        while let Some(action) = poll_action() {
+            match action {
+                Action::ToggleFilters => { out.filters.toggle(); }
+                Action::Next => return Ok(Outcome::Next),
+                Action::Prev => return Ok(Outcome::Prev),
+                Action::Quit => return Ok(Outcome::Quit),
+                _ => {}
+            }
+        }
+        if deadline.is_some_and(|d| clock::now_ns() >= d) { return Ok(Outcome::Quit) }
+        pacer.wait_next();
+    }
+}
+
 fn draw_once(fs: &mut Fs, screen: &mut Screen, path: &CStr16, blt_buf: &mut [BltPixel]) -> Result {
     // 加载文件
     let qoi_data = fs.read_file(path)?;
@@ -76,10 +245,11 @@ fn draw_once(fs: &mut Fs, screen: &mut Screen, path: &CStr16, blt_buf: &mut [Blt
 fn draw_all_mem(
     video: &mut VideoMemory,
     screen: &mut Screen,
+    out: &mut Output,
     qoi: &mut QoiFrameBuffer,
     raw: &mut RawFrameBuffer,
     blt: &mut BltFrameBuffer
-) -> Result {
+) -> Result<bool> {
     if video.next_frame(&mut qoi.0) {
         raw.header = loop {
             match qoi::decode_to_buf(&mut raw.pixels, &qoi.0) {
@@ -88,9 +258,10 @@ fn draw_all_mem(
                     raw.pixels.resize(required, 0)
                 }
                 // TODO:严重错误 真机上会出现数据错位的情况,概率极大
-                Err(e) => return Ok(()),
+                Err(e) => return Ok(true),
             }
         };
+        if !out.fits((raw.header.width, raw.header.height)) { return Ok(true) }
 
         let pixel_count = raw.get_size();
         let step = raw.header.channels.as_u8() as usize;
@@ -106,13 +277,12 @@ fn draw_all_mem(
             });
 
         // 4. 显示
-        screen.draw_image(raw.header.width, raw.header.height, &blt.0)?;
+        out.present(screen, &blt.0)?;
+        Ok(true)
     } else {
-        // 读完了，重置指针实现循环播放
-        video.rewind();
+        // 循环策略在 next_frame 里处理，走到这里就是播完了
+        Ok(false)
     }
-
-    Ok(())
 }
 
 // 目前只支持4通道互转
@@ -121,10 +291,10 @@ fn draw_all_mem_zero_copy(
     screen: &mut Screen,
     qoi: &mut QoiFrameBuffer,
     blt: &mut BltFrameBuffer
-) -> Result {
+) -> Result<bool> {
     if video.next_frame(&mut qoi.0) {
         // TODO:严重错误 真机上会出现数据错位的情况,概率极大
-        let Ok(header) = qoi::decode_header(&qoi.0) else { return Ok(()) };
+        let Ok(header) = qoi::decode_header(&qoi.0) else { return Ok(true) };
         let pixel_count = (header.width * header.height) as usize;
 
         if blt.0.len() < pixel_count {
@@ -134,7 +304,7 @@ fn draw_all_mem_zero_copy(
         // 直接解码到 [R, G, B, A, R, G, B, A...]
         // TODO:严重错误 真机上会出现数据错位的情况,概率极大
         let Ok(_) = qoi::decode_to_buf(as_u8_slice_mut(&mut blt.0[..pixel_count]), &qoi.0)
-        else { return Ok(()) };
+        else { return Ok(true) };
 
         // 交换 R 和 B
         for pixel in blt.0[..pixel_count].iter_mut() {
@@ -142,10 +312,10 @@ fn draw_all_mem_zero_copy(
         }
 
         screen.draw_image(header.width, header.height, &blt.0)?;
+        Ok(true)
     } else {
-        video.rewind();
+        Ok(false)
     }
-    Ok(())
 }
 
 fn as_u8_slice_mut(slice: &mut [BltPixel]) -> &mut [u8] {
@@ -158,12 +328,14 @@ fn as_u8_slice_mut(slice: &mut [BltPixel]) -> &mut [u8] {
 
 fn draw(
     fs: &mut Fs,
-    file: &mut RegularFile,
+    file: &mut dyn Reader,
     screen: &mut Screen,
+    out: &mut Output,
     qoi: &mut QoiFrameBuffer,
     raw: &mut RawFrameBuffer,
-    blt: &mut BltFrameBuffer
-) -> Result {
+    blt: &mut BltFrameBuffer,
+    looping: &mut LoopState,
+) -> Result<bool> {
     // 读文件流
     if fs.read_frame_next(file, &mut qoi.0)? {
         // 解码
@@ -176,9 +348,10 @@ fn draw(
                 // TODO:严重错误 真机上会出现数据错位的情况,概率极大
                 // qoi::Error::InvalidPadding
                 // qemu无问题 不知道怎么解决 暂时丢帧处理
-                Err(e) => return Ok(()),
+                Err(e) => return Ok(true),
             }
         };
+        if !out.fits((raw.header.width, raw.header.height)) { return Ok(true) }
 
         // 转换
         // 只取当前帧需要的切片范围，避免处理旧数据
@@ -191,33 +364,52 @@ fn draw(
             b.blue = r[2];
         }
 
-        screen.draw_image(raw.header.width, raw.header.height, &blt.0)?;
-    } else {
-        // 从头读
-        file.set_position(0)?;
+        out.present(screen, &blt.0)?;
+        return Ok(true);
     }
 
-    Ok(())
+    // 读到结尾，按循环策略处理
+    // 流式读取没有帧索引，倒不回去，PingPong 退化成从头读
+    match looping.on_end() {
+        AtEnd::Rewind | AtEnd::Reverse => file.rewind()?,
+        // 屏幕上留着最后一帧，什么也不做
+        AtEnd::Hold => {}
+        AtEnd::Stop => return Ok(false),
+    }
+    Ok(true)
 }
 
-type PixelPoint = u8;
-type Frame = Vec<PixelPoint>;
-type FrameSegment = Vec<Frame>;
-type CoreFrameSegment = Vec<FrameSegment>;
-
 #[repr(C)]
 struct PlayTask<'a> {
     mp: &'a MpServices, // 用于 who_am_i
     fb_base: *mut u8,
     stride_bytes: usize,
-    width: usize,
-    height: usize,
+    layout: PixelLayout, // 显存像素格式
+    rotation: Rotation,  // 面板方向，HUD 文字跟着转
+    width: usize,  // 屏幕宽（物理）
+    height: usize, // 屏幕高（物理）
     num_cores: usize,
-    // core_frames[核心ID][帧ID] -> 这一帧该核负责的像素切片
+    // frames[帧ID] -> 解码好的整帧 BGRA，各核按自己的行带从里面取
     // 注意：这里需要是指针的指针，因为 AP 无法直接访问 Vec 的元数据
-    core_frame_ptrs: *const *const *const u8,
+    frames: *const *const BltPixel,
+    frame_len: usize, // 单帧像素数
     total_frames: usize,
+    scaler: &'a Scaler, // 只读缩放表，所有核心共用
+    filters: &'a FilterChain, // 调色滤镜，融合在写显存时做
+    // scratch[核心ID] -> 一行屏幕宽度的临时缓冲，AP 上不能分配内存
+    scratch: *const *mut BltPixel,
     sync_counter: &'a AtomicUsize, // 关键：原子计数器
+    slots: &'a FrameSlots, // 每轮要画的帧号，BSP 写，所有核读
+    exited: &'a AtomicUsize, // 退出打卡，BSP 用来确认所有核都停了
+    // 播放控制只在 BSP 上读写（读键盘要用启动服务）
+    control: *mut Control,
+    pacer: *mut Pacer, // 帧调度，同样只在 BSP 上用
+    osd: *mut Osd, // OSD 状态，BSP 上更新
+    logview: *mut LogView, // 日志叠加层，同样在 BSP 上更新
+    // 叠加层（HUD、OSD），各核在写自己行带时合成
+    compositor: &'a Compositor,
+    hud: Option<PlaneId>,
+    bsp_id: usize,
 }
 
 extern "efiapi" fn play_task(arg: *mut c_void) {
@@ -227,103 +419,92 @@ extern "efiapi" fn play_task(arg: *mut c_void) {
     let my_id = unsafe { (*ctx.mp).who_am_i().unwrap_or(usize::MAX) };
     if my_id >= ctx.num_cores { return; }
 
-    // 核心参数计算
+    // 核心参数计算：按行带切分屏幕
     let rows_per_core = ctx.height / ctx.num_cores;
     let y_start = my_id * rows_per_core;
-    let my_fb_ptr = unsafe { ctx.fb_base.add(y_start * ctx.stride_bytes) };
-    let my_frames_list = unsafe { *ctx.core_frame_ptrs.add(my_id) };
-    let my_block_size = if my_id == ctx.num_cores - 1 {
-        (ctx.height - y_start) * ctx.stride_bytes
-    } else {
-        rows_per_core * ctx.stride_bytes
-    };
+    let y_end = if my_id == ctx.num_cores - 1 { ctx.height } else { y_start + rows_per_core };
+    let scratch = unsafe { core::slice::from_raw_parts_mut(*ctx.scratch.add(my_id), ctx.width) };
 
-    let mut local_frame_idx = 0;
+    let mut round = 0;
     let n_cores = ctx.num_cores;
     // 初始化第一个目标值：第一帧写完时，计数器应该达到 n_cores
     let mut my_next_target = n_cores;
 
-    // 计算帧率
-    let ticks_per_sec;
-    let target_ticks_per_frame;
-    const TARGET_FPS: u128 = 60;
-    if my_id == 0 {
-        boot::stall(Duration::from_millis(100));
-        // 采样时间太短（如 1ms）误差大，太长（如 1s）启动慢，100ms 是黄金平衡点
-        let start = unsafe { core::arch::x86_64::_rdtsc() };
-        boot::stall(Duration::from_millis(100));
-        let end = unsafe { core::arch::x86_64::_rdtsc() };
-
-        ticks_per_sec = ((end - start) * 10) as u128;
-
-        // 如果你跑的是 120Hz 视频，这里必须是 120
-        target_ticks_per_frame = ticks_per_sec / 120;
-    } else {
-        ticks_per_sec = 0;
-        target_ticks_per_frame = 0;
-    }
-
-    /// 绘制不透明字符串：位图为 1 画 color，位图为 0 画黑色
-    unsafe fn draw_string_opaque(fb_base: *mut u32, stride: usize, x: u32, y: u32, s: &[u8], color: u32) {
-        let mut current_x = x;
-        for &char_code in s {
-            // 只有 ASCII 0-127 有效
-            let glyph = FONT_8X16[(char_code & 0x7F) as usize];
-
-            for row in 0..16 {
-                let row_data = glyph[row];
-                let row_ptr = fb_base.add(((y + row as u32) * stride as u32 + current_x) as usize);
-
-                for col in 0..8 {
-                    // 直接判断位并写入，不再调用 draw_pixel 减少重复计算
-                    // FONT_8X16 通常高位在左：0x80 >> col
-                    let pixel_color = if (row_data << col) & 0x80 != 0 {
-                        color
-                    } else {
-                        0x000000 // 背景黑
-                    };
-
-                    row_ptr.add(col).write_volatile(pixel_color);
-                }
+    // 统计用时间，所有核心共用同一个单调时钟
+    let mut start_ns = clock::now_ns();
+    let mut fps_counter = 0;
+    let mut last_sample_ns = start_ns;
+
+    // 写一行到显存，有滤镜时逐点处理，否则走整行拷贝
+    // apply_filters 为 false 表示这行已经处理过滤镜（合成过叠加层的行）
+    let write = |src: &[BltPixel], y: usize, apply_filters: bool| unsafe {
+        let dst = ctx.fb_base.add(y * ctx.stride_bytes);
+        if apply_filters && ctx.filters.active() {
+            ctx.layout.write_row_map(src, dst, 0, y, |p| ctx.filters.apply(p));
+        } else {
+            ctx.layout.write_row(src, dst, 0, y);
+        }
+    };
+    loop {
+        // 0. 取本轮的帧号，BSP 发了停止就一起退出
+        let frame_idx = ctx.slots.current(round);
+        if frame_idx == STOP { break }
+
+        // 1. 搬运 (生产)：缩放 + 转换显存格式，只写自己负责的行带
+        let frame = unsafe { core::slice::from_raw_parts(*ctx.frames.add(frame_idx), ctx.frame_len) };
+        for y in y_start..y_end {
+            // 有叠加层的行先在 scratch 里合成，滤镜只作用于视频
+            if ctx.compositor.covers(round, y) {
+                let line = &mut scratch[..ctx.width];
+                ctx.scaler.render_row(frame, y, line);
+                ctx.filters.apply_slice(line);
+                ctx.compositor.blend_row(round, y, line);
+                write(line, y, false);
+            } else {
+                write(ctx.scaler.row(frame, y, scratch), y, true);
             }
-            current_x += 8;
         }
-    }
 
-    let mut start_ticks = unsafe { core::arch::x86_64::_rdtsc() };
-    let mut fps_counter = 0;
-    let mut last_sample_ticks = unsafe { core::arch::x86_64::_rdtsc() };
-    let stride = (ctx.stride_bytes / 4) as usize;
-    let ui_height = 20;
-    let start_y = ui_height;
-    let end_y = (ctx.height / 4) as usize;
-    let dst_ptr = ctx.fb_base as *mut u32;
-
-    // 计算偏移量和拷贝大小（也是固定的）
-    let offset = start_y * stride;
-    let copy_size = if end_y > start_y { (end_y - start_y) * stride } else { 0 };
-    loop {
-        // 1. 搬运 (生产)
-        unsafe {
-            if my_id == 0 {
-                // 1. 获取当前帧的源地址（这个不能移出去，因为每帧 index 不同）
-                let src_frame_base = unsafe { *my_frames_list.add(local_frame_idx) as *const u32 };
-
-                // 2. 镂空搬运
-                if copy_size > 0 {
-                    unsafe {
-                        core::ptr::copy_nonoverlapping(
-                            src_frame_base.add(offset), // 基于当前帧地址偏移
-                            dst_ptr.add(offset),        // 基于显存地址偏移
-                            copy_size
-                        );
-                    }
-                }
-            }else {
-                let src_ptr = *my_frames_list.add(local_frame_idx);
-                core::ptr::copy_nonoverlapping(src_ptr, my_fb_ptr, my_block_size);
+        // 1.5 BSP 处理按键、排帧、更新叠加层，在打卡前把下一轮发出去
+        if my_id == ctx.bsp_id {
+            let control = unsafe { &mut *ctx.control };
+            let pacer = unsafe { &mut *ctx.pacer };
+            let osd = unsafe { &mut *ctx.osd };
+            let mut running = true;
+            while let Some(action) = poll_action() {
+                if action == Action::ToggleFilters { ctx.filters.toggle(); }
+                running &= control.handle(action);
+                osd.poke();
+            }
+            // 等到下一帧该上屏的时刻，落后了就多走几帧
+            let next = if running {
+                let next = control.advance(pacer.wait_next());
+                if let Some(next) = next { pacer.note(frame_idx, next, control.paused || control.holding()) }
+                next
+            } else {
+                None
+            };
+
+            // 统计，每 64 帧刷新一次 HUD
+            let end_ns = clock::now_ns();
+            let ft_us = (end_ns - start_ns) / 1000;
+            let margin_us = pacer.slack_ns / 1000;
+            start_ns = end_ns;
+            fps_counter += 1;
+            if (fps_counter & 63) == 0 {
+                // 既然是 64 帧更新一次，公式就是 64 秒 / 总耗时
+                let total_span = end_ns.saturating_sub(last_sample_ns).max(1);
+                let fps = 64 * 1_000_000_000 / total_span;
+                last_sample_ns = end_ns;
+                if let Some(hud) = ctx.hud { draw_hud(ctx.compositor, hud, fps, ft_us, margin_us, pacer, control) }
             }
 
+            osd.update(ctx.compositor, control);
+            // AP 打的日志在这里取走
+            logger::flush();
+            unsafe { (*ctx.logview).update(ctx.compositor) };
+            ctx.compositor.sync(round);
+            ctx.slots.publish_next(round, next.unwrap_or(STOP));
         }
 
         // 2. 打卡 (原子加法)
@@ -336,195 +517,216 @@ extern "efiapi" fn play_task(arg: *mut c_void) {
             core::hint::spin_loop();
         }
 
-        // 4. 只有 0 号核做统计（可选，且不影响同步）
-        if my_id == 0 {
-            let end_ticks = unsafe { core::arch::x86_64::_rdtsc() };
-            let delta_ticks = (end_ticks - start_ticks) as u128;
+        // 4. 为下一轮做准备 (全是加法)
+        my_next_target += n_cores;
+        round += 1;
+    }
 
-            // 1. 计算当前帧耗时和余量
-            let ft_us = (delta_ticks * 1_000_000) / ticks_per_sec;
-            let margin_us = if target_ticks_per_frame > delta_ticks {
-                ((target_ticks_per_frame - delta_ticks) * 1_000_000) / ticks_per_sec
-            } else {
-                0
-            };
+    // 5. 退出打卡，之后不能再碰 ctx 里的任何东西
+    ctx.exited.fetch_add(1, Ordering::Release);
+}
 
-            fps_counter += 1;
-            // 每 64 帧更新一次显示
-            if (fps_counter & 63) == 0 {
-                // --- 核心修正：计算被漏掉的 fps ---
-                let total_span = end_ticks.saturating_sub(last_sample_ticks);
-                // 既然是 64 帧更新一次，公式就是 (64 * 频率) / 总耗时
-                let fps = (64 * ticks_per_sec) / (total_span as u128);
-
-                last_sample_ticks = end_ticks;
-
-                let fb = ctx.fb_base as *mut u32;
-                // 注意：stride 必须是像素跨度，确保你在 loop 外已经算好了 stride = stride_bytes / 4
-
-                // --- 绘制逻辑 ---
-                let fps_str = format!("FPS: {:>4}", fps);
-                let ft_str  = format!("FT: {:>5} us", ft_us);
-                let mg_str  = format!("Margin: {:>5} us", margin_us);
-
-                unsafe {
-                    // 并排显示在最顶层 (y=0)
-                    draw_string_opaque(fb, stride, 0,   0, fps_str.as_bytes(), 0x00FF00); // 绿色
-                    draw_string_opaque(fb, stride, 200, 0, ft_str.as_bytes(), 0x00FFFF);  // 青色
-                    draw_string_opaque(fb, stride, 450, 0, mg_str.as_bytes(), 0xFFA500);  // 橙色
-                }
-            }
+// HUD 高度，逻辑像素，一行字上下各留 2 像素
+fn hud_height() -> usize {
+    font::cell().1 + 4
+}
 
-            start_ticks = end_ticks;
-        }
+/// 把统计信息画进 HUD 层，只在 BSP 上调用
+fn draw_hud(compositor: &Compositor, hud: PlaneId, fps: u64, ft_us: u64, margin_us: u64, pacer: &Pacer, control: &Control) {
+    let fps_str = format!("FPS: {:>4}", fps);
+    let ft_str  = format!("FT: {:>5} us", ft_us);
+    let mg_str  = format!("Margin: {:>5} us", margin_us);
+    let pc_str  = format!("Drop: {} Rep: {}", pacer.dropped, pacer.repeated);
+    let st_str = if control.paused {
+        format!("PAUSE {:>6}", control.frame())
+    } else {
+        format!("{:>3}% {:>6}", control.speed_percent(), control.frame())
+    };
 
-        // 5. 为下一轮做准备 (全是加法)
-        my_next_target += n_cores;
-        local_frame_idx += 1;
-        if local_frame_idx >= ctx.total_frames {
-            local_frame_idx  = 0;
-        }
-    }
+    compositor.draw(hud, |c| {
+        c.clear(BltPixel::new(0, 0, 0));
+        // 并排显示在最顶层，按字符格排列
+        let (cw, y) = (font::cell().0, 2);
+        c.text(0,        y, &fps_str, BltPixel::from(0x00FF00)); // 绿色
+        c.text(25 * cw,  y, &ft_str,  BltPixel::from(0x00FFFF)); // 青色
+        c.text(56 * cw,  y, &mg_str,  BltPixel::from(0xFFA500)); // 橙色
+        c.text(81 * cw,  y, &st_str,  BltPixel::from(0xFFFFFF)); // 白色
+        c.text(100 * cw, y, &pc_str,  BltPixel::from(0xFF5050)); // 红色
+    });
+    compositor.show(hud, true);
 }
 
-pub fn mp_draw(screen: &mut Screen, file: &mut RegularFile, width: usize, height: usize) -> Result {
+/// title 显示在 OSD 上，looping 为播完以后怎么办，到了 deadline (单调时钟纳秒) 直接退出
+/// 返回这个视频为什么停下
+pub fn mp_draw(screen: &mut Screen, file: &mut dyn Reader, width: usize, height: usize, scaler: Scaler, filters: FilterChain, config: &Config, title: &str, looping: LoopMode, deadline: Option<u64>) -> Result<Outcome> {
     // 1 解码
     let mp_handle = get_handle_for_protocol::<MpServices>()?;
     let mp = open_protocol_exclusive::<MpServices>(mp_handle)?;
-    let n_cores = mp.get_number_of_processors()?.enabled;
-    let mut info_buf = vec![0u8; 128];
-    let info = loop {
-        match file.get_info::<FileInfo>(&mut info_buf) {
-            Ok(info) => break info,
-            Err(e) if e.status() == Status::BUFFER_TOO_SMALL => {
-                if let Some(size) = *e.data() { info_buf.resize(size, 0) }
-            }
-            Err(e) => return Err(e.status())?,
-        }
-    };
-    let mut compressed_buffer = vec![0u8; info.file_size() as usize];
-    file.read(&mut compressed_buffer).map_err(|e| e.status())?;
-
-    // 准备核心存储容器: [核心ID][帧ID] -> 这一帧该核负责的像素切片
-    let mut core_frames: CoreFrameSegment = (0..n_cores).map(|_| Vec::new()).collect();
-    let mut offset = 0;
+    let enabled = mp.get_number_of_processors()?.enabled;
+    let n_cores = if config.cores == 0 { enabled } else { config.cores.clamp(1, enabled) };
+    let compressed_buffer = file.read_all()?;
+
+    // 预解码成整帧 BGRA，分辨率对不上的坏帧直接丢掉
+    let mut video = VideoMemoryRaw::from_bytes(&compressed_buffer);
+    drop(compressed_buffer);
+    video.frames.retain(|f| f.len() == width * height);
+    if video.frames.is_empty() {
+        Err(Status::INVALID_PARAMETER)?
+    }
 
     let (scr_width, scr_height) = screen.get_gop().current_mode_info().resolution();
-    let rows_per_core = scr_height / n_cores;
-
-    let fb_base = screen.get_gop().frame_buffer().as_mut_ptr();
-    let scr_stride = screen.get_gop().current_mode_info().stride();
-
-    // 原始单帧空间初始化
-    let mut single_raw: Frame = vec![0u8; width * height * 4];
-    // 文件末尾越界保护
-    while offset + 4 <= compressed_buffer.len() {
-        let len_bytes = &compressed_buffer[offset..offset + 4];
-        let frame_len =
-            u32::from_le_bytes([len_bytes[0], len_bytes[1], len_bytes[2], len_bytes[3]]) as usize;
-        let data_start = offset + 4;
-        let next_frame_pos = data_start + frame_len;
-
-        if next_frame_pos > compressed_buffer.len() { break }
-
-        // 获取当前Qoi帧
-        let frame_data = &compressed_buffer[data_start..next_frame_pos];
-
-        if qoi::decode_to_buf(&mut single_raw, frame_data).is_err() {
-            offset = next_frame_pos;
-            continue
-        }
-
-        // 红蓝交换 (RGBA -> BGRA)
-        for chunk in single_raw.chunks_exact_mut(4) {
-            chunk.swap(0, 2);
-        }
-
-        // 核心切分
-        for core_id in 0..n_cores {
-            let y_start = core_id * rows_per_core; // 该核心负责的屏幕起始行
-            let y_end = if core_id == n_cores - 1 { scr_height } else { y_start + rows_per_core };
-
-            let mut frame: Frame = Vec::new();
-            let stride_bytes = scr_stride * 4; // 屏幕物理跨度
-            let row_bytes = width * 4;        // 视频实际宽度
-
-            for y in y_start..y_end {
-                let video_y = y;
-
-                if video_y < height {
-                    // 只有当屏幕行落在视频高度范围内时，才去 single_raw 拿数据
-                    let src_row_start = video_y * width * 4;
-                    let src_row_end = src_row_start + row_bytes;
-
-                    if src_row_end > single_raw.len() {
-                        Err(Status::INVALID_PARAMETER)?
-                    }
-
-                    // 1. 拷贝视频行
-                    frame.extend_from_slice(&single_raw[src_row_start..src_row_end]);
-
-                    // 2. 补齐当前行到屏幕的 stride 跨度
-                    if stride_bytes > row_bytes {
-                        let padding = stride_bytes - row_bytes;
-                        frame.resize(frame.len() + padding, 0u8);
-                    }
-                } else {
-                    // 如果屏幕行超过了视频高度，这一行全是黑的（但也要占满 stride 长度）
-                    frame.resize(frame.len() + stride_bytes, 0u8);
-                }
+    let mut control = Control::new(video.frames.len(), config.fps, looping);
+    control.deadline_ns = deadline;
+    let refresh = screen.refresh_rate();
+    if let Some(mhz) = refresh { log::info!("display refresh: {}.{:03} Hz", mhz / 1000, mhz % 1000) }
+    let mut pacer = Pacer::new(config.fps, refresh);
+    // 叠加层从下往上：HUD 在顶边，日志在 HUD 下面，OSD 在底边
+    let mut compositor = Compositor::new(screen.rotation(), (scr_width, scr_height));
+    let (logical_w, _) = compositor.logical_size();
+    // 开机动画不显示统计
+    let hud = (config.hud && config.chain.is_none()).then(|| compositor.add((0, 0, logical_w, hud_height()), 192, None));
+    let mut osd = Osd::new(&mut compositor, title, video.frames.len(), config.fps);
+    let mut logview = LogView::new(&mut compositor, hud_height());
+
+    // 没有线性显存：单核缩放后走 BufferToVideo，HUD 和多核路径一样每 64 帧刷新
+    let layout = screen.layout();
+    if !layout.has_framebuffer() {
+        let mut canvas = Vec::new();
+        let mut frame_idx = 0;
+        let mut start_ns = clock::now_ns();
+        let mut last_sample_ns = start_ns;
+        for round in 0.. {
+            screen.present_scaled(&video.frames[frame_idx], &scaler, &filters, Some((&compositor, round)), &mut canvas)?;
+            while let Some(action) = poll_action() {
+                if action == Action::ToggleFilters { filters.toggle(); }
+                if !control.handle(action) { return Ok(control.outcome().unwrap_or(Outcome::Quit)) }
+                osd.poke();
+            }
+            // 超时和播完都记在 control 里，和多核路径一样从那里取
+            let Some(next) = control.advance(pacer.wait_next()) else { return Ok(control.outcome().unwrap_or(Outcome::Quit)) };
+            pacer.note(frame_idx, next, control.paused || control.holding());
+
+            let end_ns = clock::now_ns();
+            let ft_us = (end_ns - start_ns) / 1000;
+            start_ns = end_ns;
+            if (round + 1) & 63 == 0 {
+                let fps = 64 * 1_000_000_000 / end_ns.saturating_sub(last_sample_ns).max(1);
+                last_sample_ns = end_ns;
+                if let Some(hud) = hud { draw_hud(&compositor, hud, fps, ft_us, pacer.slack_ns / 1000, &pacer, &control) }
             }
-            core_frames[core_id].push(frame);
+            osd.update(&compositor, &control);
+            logview.update(&compositor);
+            compositor.sync(round);
+            frame_idx = next;
         }
-        offset = next_frame_pos;
     }
 
     // 2 构造参数
-    let stride_bytes = scr_stride * 4;
-
-    // 用来存储所有核心任务包的指针
-
-    // --- 准备指针矩阵 ---
-    // 我们需要把 Vec<Vec<Vec<u8>>> 转换成三级指针，方便 AP 访问
-    let mut all_core_lists = Vec::new();
-    for core_id in 0..n_cores {
-        let frame_addrs: Vec<*const u8> = core_frames[core_id].iter().map(|f| f.as_ptr()).collect();
-        all_core_lists.push(Box::leak(frame_addrs.into_boxed_slice()).as_ptr());
-    }
-    let core_frame_ptrs = Box::leak(all_core_lists.into_boxed_slice()).as_ptr();
-
-    let (scr_width, scr_height) = screen.get_gop().current_mode_info().resolution();
     let scr_stride = screen.get_gop().current_mode_info().stride();
+    let stride_bytes = scr_stride * layout.bytes_per_pixel();
     let fb_base = screen.get_gop().frame_buffer().as_mut_ptr();
 
-    let sync_counter = Box::leak(Box::new(AtomicUsize::new(0)));
+    // --- 准备共享数据 ---
+    // AP 上的代码只认裸指针，这些都放在堆上，正常退出时随函数结束释放
+    // 我们需要把 Vec<Vec<BltPixel>> 转换成二级指针，方便 AP 访问
+    let frame_addrs: Vec<*const BltPixel> = video.frames.iter().map(|f| f.as_ptr()).collect();
+
+    // 每个核心一行的临时缓冲，缩放结果先落在这里再转格式写显存
+    let mut scratch_rows: Vec<Vec<BltPixel>> = (0..n_cores).map(|_| vec![BltPixel::new(0, 0, 0); scr_width]).collect();
+    let scratch_addrs: Vec<*mut BltPixel> = scratch_rows.iter_mut().map(|r| r.as_mut_ptr()).collect();
+
+    let sync_counter = Box::new(AtomicUsize::new(0));
+    let slots = Box::new(FrameSlots::new(0));
+    let exited = Box::new(AtomicUsize::new(0));
+    let scaler = Box::new(scaler);
+    let filters = Box::new(filters);
+    let mut control = Box::new(control);
+    let mut pacer = Box::new(pacer);
+    let mut osd = Box::new(osd);
+    let mut logview = Box::new(logview);
+    let compositor = Box::new(compositor);
 
     // --- 构造统一 Context ---
-    let ctx = Box::leak(Box::new(PlayTask {
+    let mut ctx = Box::new(PlayTask {
         mp: &mp,
         fb_base,
-        stride_bytes: scr_stride * 4,
-        width,
+        stride_bytes,
+        layout,
+        rotation: screen.rotation(),
+        width: scr_width,
         height: scr_height,
         num_cores: n_cores,
-        core_frame_ptrs,
-        total_frames: core_frames[0].len(),
-        sync_counter
-    }));
-
-    let arg_ptr = ctx as *mut _ as *mut c_void;
+        frames: frame_addrs.as_ptr(),
+        frame_len: width * height,
+        total_frames: video.frames.len(),
+        scaler: &scaler,
+        filters: &filters,
+        scratch: scratch_addrs.as_ptr(),
+        sync_counter: &sync_counter,
+        slots: &slots,
+        exited: &exited,
+        control: &mut *control,
+        pacer: &mut *pacer,
+        osd: &mut *osd,
+        logview: &mut *logview,
+        compositor: &compositor,
+        hud,
+        bsp_id: mp.who_am_i()?,
+    });
+
+    let arg_ptr = &mut *ctx as *mut PlayTask as *mut c_void;
 
     let event = unsafe { create_event(EventType::empty(), Tpl::CALLBACK, None, None)? };
 
     // --- 启动 AP ---
+    let mut aps_started = false;
     if n_cores > 1 {
-        // 使用你彩色代码中成功的 startup_all_aps
         // 注意：如果你需要它不阻塞持续播放，这里要设置为 false (非阻塞启动)
-        let _ = mp.startup_all_aps(false, play_task, arg_ptr, Some(event), None);
+        match mp.startup_all_aps(false, play_task, arg_ptr, Some(unsafe { event.unsafe_clone() }), None) {
+            Ok(()) => aps_started = true,
+            // AP 起不来就 BSP 一个人画整屏，不然屏障永远等不齐
+            Err(e) => {
+                log::warn!("startup_all_aps failed: {:?}, playing on BSP only", e.status());
+                ctx.num_cores = 1;
+            }
+        }
     }
 
-    // --- BSP 亲自执行 ---
+    // --- BSP 亲自执行，直到用户退出 ---
     play_task(arg_ptr);
 
-    Ok(())
-}
\ No newline at end of file
+    // --- 等所有核心退出 ---
+    // 先等打卡，再等固件确认 AP 的任务函数都返回了
+    let cores = ctx.num_cores;
+    let acked = wait_until(AP_EXIT_TIMEOUT, || exited.load(Ordering::Acquire) >= cores);
+    let finished = acked && (!aps_started || wait_until(AP_EXIT_TIMEOUT, || {
+        boot::check_event(unsafe { event.unsafe_clone() }).unwrap_or(false)
+    }));
+
+    if !finished {
+        // 还有 AP 没回来，它可能还在读这些数据，只能放着不还
+        log::warn!("APs did not stop in time, leaking playback state");
+        core::mem::forget(ctx);
+        core::mem::forget((event, frame_addrs, scratch_rows, scratch_addrs, sync_counter, slots, exited));
+        core::mem::forget((scaler, filters, control, pacer, osd, logview, compositor, video, mp));
+        return Err(Status::TIMEOUT.into());
+    }
+
+    boot::close_event(event)?;
+    Ok(control.outcome().unwrap_or(Outcome::Quit))
+}
+
+// AP 退出的等待上限
+const AP_EXIT_TIMEOUT: Duration = Duration::from_secs(2);
+
+// 轮询直到条件成立，超时返回 false
+fn wait_until(timeout: Duration, mut done: impl FnMut() -> bool) -> bool {
+    const STEP: Duration = Duration::from_millis(1);
+    let mut waited = Duration::ZERO;
+    while !done() {
+        if waited >= timeout { return false }
+        boot::stall(STEP);
+        waited += STEP;
+    }
+    true
+}
@@ -0,0 +1,242 @@
+use core::sync::atomic::{AtomicUsize, Ordering};
+use crate::clock;
+use crate::input::Action;
+use crate::video::looping::{AtEnd, LoopMode, LoopState};
+
+/// 发布给所有核心的帧号里表示"停止"的值
+pub const STOP: usize = usize::MAX;
+
+// 播放速度,单位 1/4 帧每帧:0.25x 0.5x 1x 1.5x 2x 4x
+const SPEEDS: [u64; 6] = [1, 2, 4, 6, 8, 16];
+const NORMAL_SPEED: usize = 2;
+const QUARTER: u64 = 4;
+
+/// 一个视频为什么停下,决定播放列表接下来怎么走
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// 按循环次数播完了
+    Finished,
+    Next,
+    Prev,
+    Quit,
+}
+
+/// 多核之间共享的每轮帧号
+/// 第 r 轮所有核心读 slots[r & 1],BSP 在第 r 轮到达屏障前写 slots[(r + 1) & 1]
+/// 这样 BSP 写下一轮的时候,慢的 AP 还能安全地读本轮
+pub struct FrameSlots {
+    slots: [AtomicUsize; 2],
+}
+
+impl FrameSlots {
+    pub fn new(first: usize) -> Self {
+        Self { slots: [AtomicUsize::new(first), AtomicUsize::new(first)] }
+    }
+
+    #[inline(always)]
+    pub fn current(&self, round: usize) -> usize {
+        self.slots[round & 1].load(Ordering::Acquire)
+    }
+
+    #[inline(always)]
+    pub fn publish_next(&self, round: usize, frame: usize) {
+        self.slots[(round + 1) & 1].store(frame, Ordering::Release)
+    }
+}
+
+/// BSP 私有的播放控制状态,AP 只通过 FrameSlots 看到结果
+pub struct Control {
+    pub paused: bool,
+    speed: usize,
+    // 播放位置,单位 1/4 帧,变速时可以走半帧
+    position: u64,
+    total: usize,
+    fps: usize,
+    looping: LoopState,
+    outcome: Option<Outcome>,
+    /// 到这个时刻 (clock::now_ns) 就退出,开机动画超时用
+    pub deadline_ns: Option<u64>,
+}
+
+impl Control {
+    pub fn new(total: usize, fps: usize, looping: LoopMode) -> Self {
+        Self {
+            paused: false,
+            speed: NORMAL_SPEED,
+            position: 0,
+            total: total.max(1),
+            fps: fps.max(1),
+            looping: LoopState::new(looping),
+            outcome: None,
+            deadline_ns: None,
+        }
+    }
+
+    /// 停下的原因,还在播时为 None
+    #[inline]
+    pub fn outcome(&self) -> Option<Outcome> { self.outcome }
+
+    #[inline]
+    pub fn frame(&self) -> usize { (self.position / QUARTER) as usize }
+
+    /// 当前倍速 ×100
+    pub fn speed_percent(&self) -> u64 { SPEEDS[self.speed] * 100 / QUARTER }
+
+    /// 处理一个动作,返回 false 表示要退出
+    pub fn handle(&mut self, action: Action) -> bool {
+        match action {
+            Action::TogglePause => self.paused = !self.paused,
+            Action::SeekSeconds(s) => self.seek_frames(s as i64 * self.fps as i64),
+            Action::Step(n) => {
+                self.paused = true;
+                self.seek_frames(n as i64);
+            }
+            Action::SpeedUp => self.speed = (self.speed + 1).min(SPEEDS.len() - 1),
+            Action::SpeedDown => self.speed = self.speed.saturating_sub(1),
+            Action::Restart => {
+                self.position = 0;
+                self.looping.reset();
+                self.paused = false;
+            }
+            Action::Next => return self.stop(Outcome::Next),
+            Action::Prev => return self.stop(Outcome::Prev),
+            Action::Quit => return self.stop(Outcome::Quit),
+            // 滤镜开关不影响进度,由调用方处理
+            Action::ToggleFilters => {}
+        }
+        true
+    }
+
+    /// 内容时间线走过 frames 帧,返回下一帧帧号
+    /// 到头按循环策略处理,播完或超时返回 None
+    pub fn advance(&mut self, frames: u64) -> Option<usize> {
+        // 超时不管暂停与否
+        if self.deadline_ns.is_some_and(|d| clock::now_ns() >= d) {
+            self.stop(Outcome::Quit);
+            return None;
+        }
+        if self.paused { return Some(self.frame()) }
+
+        let span = self.total as i64 * QUARTER as i64;
+        let last = span - QUARTER as i64;
+        let delta = (frames * SPEEDS[self.speed]) as i64;
+        let mut pos = self.position as i64 + if self.looping.reverse() { -delta } else { delta };
+
+        // 只有一帧时没有来回可言,每次都算走完一遍
+        if last == 0 {
+            if self.looping.on_end() == AtEnd::Stop { return self.finish() }
+            return Some(0);
+        }
+        // 一次可能走过不止一遍(倍速跳帧),循环到落在范围内为止
+        while pos < 0 || pos > last {
+            pos = match self.looping.on_end() {
+                AtEnd::Rewind if pos > last => pos - span,
+                AtEnd::Rewind => pos + span,
+                // 以端点为镜面反射回来
+                AtEnd::Reverse if pos > last => 2 * last - pos,
+                AtEnd::Reverse => -pos,
+                AtEnd::Hold => pos.clamp(0, last),
+                AtEnd::Stop => return self.finish(),
+            };
+        }
+        self.position = pos as u64;
+        Some(self.frame())
+    }
+
+    /// Hold 模式停在最后一帧上
+    pub fn holding(&self) -> bool {
+        self.looping.holding() && self.frame() + 1 == self.total
+    }
+
+    fn finish(&mut self) -> Option<usize> {
+        self.stop(Outcome::Finished);
+        None
+    }
+
+    fn stop(&mut self, outcome: Outcome) -> bool {
+        self.outcome = Some(outcome);
+        false
+    }
+
+    // 跳转并夹在 [0, total) 里,往前跳过头就停在第一帧
+    fn seek_frames(&mut self, delta: i64) {
+        let frame = (self.frame() as i64 + delta).clamp(0, self.total as i64 - 1);
+        self.position = frame as u64 * QUARTER;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 按正常速度一帧一帧地走,记下每一步的结果
+    fn run(control: &mut Control, n: usize) -> Vec<Option<usize>> {
+        let mut out = Vec::new();
+        for _ in 0..n {
+            let next = control.advance(1);
+            out.push(next);
+            if next.is_none() { break }
+        }
+        out
+    }
+
+    #[test]
+    fn once_finishes() {
+        let mut control = Control::new(4, 30, LoopMode::Once);
+        assert_eq!(run(&mut control, 6), [Some(1), Some(2), Some(3), None]);
+        assert_eq!(control.outcome(), Some(Outcome::Finished));
+    }
+
+    #[test]
+    fn times_rewinds_then_finishes() {
+        let mut control = Control::new(3, 30, LoopMode::Times(2));
+        assert_eq!(run(&mut control, 8), [Some(1), Some(2), Some(0), Some(1), Some(2), None]);
+        assert_eq!(control.outcome(), Some(Outcome::Finished));
+    }
+
+    #[test]
+    fn forever_keeps_rewinding() {
+        let mut control = Control::new(3, 30, LoopMode::Forever);
+        assert_eq!(run(&mut control, 9), [1, 2, 0, 1, 2, 0, 1, 2, 0].map(Some));
+        assert_eq!(control.outcome(), None);
+    }
+
+    #[test]
+    fn pingpong_reflects_at_both_ends() {
+        let mut control = Control::new(4, 30, LoopMode::PingPong);
+        assert_eq!(run(&mut control, 9), [1, 2, 3, 2, 1, 0, 1, 2, 3].map(Some));
+    }
+
+    #[test]
+    fn pingpong_turnaround_at_double_speed() {
+        let mut control = Control::new(4, 30, LoopMode::PingPong);
+        control.handle(Action::SpeedUp);
+        control.handle(Action::SpeedUp);
+        // 2x 每步两帧,越过端点的部分以端点为镜面反射回来
+        assert_eq!(run(&mut control, 5), [2, 2, 0, 2, 2].map(Some));
+    }
+
+    #[test]
+    fn hold_stays_on_last_frame() {
+        let mut control = Control::new(3, 30, LoopMode::Hold);
+        assert_eq!(run(&mut control, 2), [Some(1), Some(2)]);
+        assert!(!control.holding());
+        assert_eq!(run(&mut control, 3), [Some(2); 3]);
+        assert!(control.holding());
+        assert_eq!(control.outcome(), None);
+    }
+
+    #[test]
+    fn single_frame_counts_passes() {
+        let mut control = Control::new(1, 30, LoopMode::Times(3));
+        assert_eq!(run(&mut control, 5), [Some(0), Some(0), None]);
+    }
+
+    #[test]
+    fn paused_does_not_move() {
+        let mut control = Control::new(4, 30, LoopMode::Once);
+        control.advance(1);
+        control.handle(Action::TogglePause);
+        assert_eq!(run(&mut control, 3), [Some(1); 3]);
+    }
+}
@@ -1,90 +1,89 @@
 use alloc::vec;
 use alloc::vec::Vec;
 use uefi::proto::console::gop::BltPixel;
-use uefi::proto::media::file::{File, FileInfo, RegularFile};
-use uefi::Status;
+use crate::error::Result;
+use crate::fs::Reader;
+use crate::video::looping::{LoopMode, LoopState};
+
+/// 读取第一帧的 QOI 头拿到视频分辨率，读完把文件指针拨回开头
+pub fn probe_resolution(file: &mut dyn Reader) -> Result<(usize, usize)> {
+    // 4 字节帧长 + 14 字节 QOI 头
+    let mut head = [0u8; 4 + qoi::consts::QOI_HEADER_SIZE];
+    let read = file.read_chunk(&mut head)?;
+    file.rewind()?;
+    let header = qoi::decode_header(&head[4..read.max(4)])?;
+    Ok((header.width as usize, header.height as usize))
+}
 
 ///////// 全部写入内存
 pub struct VideoMemory {
     pub data: Vec<u8>,
+    // 每帧在 data 里的起点（指向 4 字节长度），倒着播要用
+    offsets: Vec<usize>,
+    /// 下一帧的编号
     pub cursor: usize,
+    /// 播到结尾以后怎么办，默认一直循环
+    pub looping: LoopState,
 }
 
 impl VideoMemory {
-    pub fn new(mut file: RegularFile) -> crate::error::Result<Self> {
-        let mut info_buf = vec![0u8; 128];
-        let info = loop {
-            match file.get_info::<FileInfo>(&mut info_buf) {
-                Ok(info) => break info,
-                Err(e) if e.status() == Status::BUFFER_TOO_SMALL =>
-                    if let Some(size) = *e.data() {
-                        info_buf.resize(size, 0)
-                    },
-                // 其他状态的Option均为None,这里拉平
-                Err(e) => Err(e.status())?,
-            }
-        };
-        let size = info.file_size() as usize;
-
-        // 一次性分配内存并读取
-        let mut buffer = vec![0u8; size];
-        file.read(&mut buffer)?;
+    pub fn new(file: &mut dyn Reader) -> crate::error::Result<Self> {
+        // 一次性读进内存
+        let buffer = file.read_all()?;
+
+        // 先扫一遍帧边界，截断的尾帧直接丢掉
+        let mut offsets = Vec::new();
+        let mut pos = 0;
+        while pos + 4 <= buffer.len() {
+            let frame_len = u32::from_le_bytes([buffer[pos], buffer[pos + 1], buffer[pos + 2], buffer[pos + 3]]) as usize;
+            if pos + 4 + frame_len > buffer.len() { break }
+            offsets.push(pos);
+            pos += 4 + frame_len;
+        }
 
         Ok(Self {
             data: buffer,
+            offsets,
             cursor: 0,
+            looping: LoopState::new(LoopMode::Forever),
         })
     }
 
     /// 模仿之前的 read_frame_next，但改为从内存切片
+    /// 返回 false 表示按循环策略播完了
     pub fn next_frame(&mut self, qoi_buf: &mut Vec<u8>) -> bool {
-        if self.cursor >= self.data.len() {
-            return false;
-        }
+        let Some(&start) = self.offsets.get(self.cursor) else { return false };
 
-        // 假设你的每帧 QOI 数据前面有 4 字节长度信息（这是常见的拼接方式）
-        let len_bytes = &self.data[self.cursor..self.cursor + 4];
+        // 每帧 QOI 数据前面有 4 字节长度信息
+        let len_bytes = &self.data[start..start + 4];
         let frame_len = u32::from_le_bytes([len_bytes[0], len_bytes[1], len_bytes[2], len_bytes[3]]) as usize;
 
-        self.cursor += 4;
-        let end = self.cursor + frame_len;
-
         // 将这一帧的数据拷贝到 qoi_buf
         qoi_buf.clear();
-        qoi_buf.extend_from_slice(&self.data[self.cursor..end]);
+        qoi_buf.extend_from_slice(&self.data[start + 4..start + 4 + frame_len]);
 
-        self.cursor = end;
+        self.cursor = self.looping.step(self.cursor, self.offsets.len()).unwrap_or(usize::MAX);
         true
     }
-
-    pub fn rewind(&mut self) {
-        self.cursor = 0;
-    }
 }
 
 //////// 原始数据全缓存
 pub struct VideoMemoryRaw {
     // 存储所有帧的像素数据，每一项都是一帧完整的 BltPixel 数组
     pub frames: Vec<Vec<BltPixel>>,
+    /// 下一帧的编号
     pub cursor: usize,
+    /// 播到结尾以后怎么办，默认一直循环
+    pub looping: LoopState,
 }
 
 impl VideoMemoryRaw {
-    pub fn new(mut file: RegularFile) -> crate::error::Result<Self> {
-        let mut info_buf = vec![0u8; 128];
-        let info = loop {
-            match file.get_info::<FileInfo>(&mut info_buf) {
-                Ok(info) => break info,
-                Err(e) if e.status() == Status::BUFFER_TOO_SMALL => {
-                    if let Some(size) = *e.data() { info_buf.resize(size, 0) }
-                }
-                Err(e) => return Err(e.status())?,
-            }
-        };
-
-        let mut compressed_buffer = vec![0u8; info.file_size() as usize];
-        file.read(&mut compressed_buffer)?;
+    pub fn new(file: &mut dyn Reader) -> crate::error::Result<Self> {
+        Ok(Self::from_bytes(&file.read_all()?))
+    }
 
+    /// 从已经读进内存的 qois 数据预解码
+    pub fn from_bytes(compressed_buffer: &[u8]) -> Self {
         // 预解码
         let mut frames = Vec::new();
         let mut offset = 0;
@@ -132,23 +131,21 @@ impl VideoMemoryRaw {
             offset = next_frame_pos;
         }
 
-        Ok(Self {
+        Self {
             frames,
             cursor: 0,
-        })
+            looping: LoopState::new(LoopMode::Forever),
+        }
     }
 
     /// 极致性能：直接返回当前帧的像素引用，完全无拷贝，无解码
+    /// 返回 None 表示按循环策略播完了
     pub fn next_frame(&mut self) -> Option<&[BltPixel]> {
-        if self.cursor >= self.frames.len() {
+        let index = self.cursor;
+        if index >= self.frames.len() {
             return None;
         }
-        let frame = &self.frames[self.cursor];
-        self.cursor += 1;
-        Some(frame)
-    }
-
-    pub fn rewind(&mut self) {
-        self.cursor = 0;
+        self.cursor = self.looping.step(index, self.frames.len()).unwrap_or(usize::MAX);
+        Some(&self.frames[index])
     }
 }
\ No newline at end of file
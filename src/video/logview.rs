@@ -0,0 +1,74 @@
+use alloc::format;
+use log::{Level, LevelFilter};
+use uefi::proto::console::gop::BltPixel;
+use crate::clock;
+use crate::graphics::console;
+use crate::graphics::font;
+use crate::graphics::overlay::{Compositor, PlaneId};
+use crate::logger;
+
+// 最多显示几条
+const LINES: usize = 4;
+const PAD: usize = 4;
+// 有新日志后显示多久
+const LOG_TIMEOUT_NS: u64 = 5_000_000_000;
+const LOG_ALPHA: u8 = 192;
+
+const BG: BltPixel = BltPixel::new(0, 0, 0);
+
+/// 播放时把最近几条日志叠在画面上,级别由 [log] console 决定,只在 BSP 上用
+/// AP 打的日志也在里面,下一轮 BSP 更新时出现
+pub struct LogView {
+    plane: PlaneId,
+    // 上次看到的日志总数,没变就不用去环里找
+    seen: usize,
+    // 上次画的最后一条
+    shown: Option<usize>,
+    visible_until: u64,
+}
+
+impl LogView {
+    /// 放在 HUD 下面,top 为上边的逻辑 y
+    pub fn new(compositor: &mut Compositor, top: usize) -> Self {
+        let (lw, lh) = compositor.logical_size();
+        let height = (LINES * console::cell().1 + 2 * PAD).min(lh.saturating_sub(top));
+        let plane = compositor.add((0, top, lw, height), LOG_ALPHA, None);
+        Self { plane, seen: 0, shown: None, visible_until: 0 }
+    }
+
+    /// 每轮在 compositor.sync 之前调用
+    pub fn update(&mut self, compositor: &Compositor) {
+        if logger::console_level() == LevelFilter::Off { return }
+        let now = clock::now_ns();
+        let head = logger::head();
+        if head != self.seen {
+            self.seen = head;
+            let entries = logger::recent(LINES);
+            let last = entries.last().map(|e| e.seq);
+            // 新来的都是不够级别的,不用重画
+            if last != self.shown {
+                self.shown = last;
+                self.visible_until = now + LOG_TIMEOUT_NS;
+                compositor.draw(self.plane, |c| {
+                    let (cw, ch) = console::cell();
+                    let cols = c.size().0.saturating_sub(2 * PAD) / cw;
+                    c.clear(BG);
+                    for (i, entry) in entries.iter().enumerate() {
+                        let line = format!("{:<5} {}", entry.level, entry.text());
+                        c.text(PAD, PAD + i * ch, font::clip(&line, cols), color(entry.level));
+                    }
+                });
+            }
+        }
+        compositor.show(self.plane, self.shown.is_some() && now < self.visible_until);
+    }
+}
+
+fn color(level: Level) -> BltPixel {
+    match level {
+        Level::Error => BltPixel::new(255, 85, 85),
+        Level::Warn => BltPixel::new(255, 255, 85),
+        Level::Info => BltPixel::new(230, 230, 230),
+        _ => BltPixel::new(150, 150, 150),
+    }
+}
@@ -0,0 +1,162 @@
+/// 播到结尾以后怎么办
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoopMode {
+    /// 播一遍就停
+    Once,
+    /// 播 n 遍
+    Times(u32),
+    Forever,
+    /// 正着播完倒着播,来回往复
+    PingPong,
+    /// 停在最后一帧不动,等用户切走
+    Hold,
+}
+
+impl LoopMode {
+    /// 配置里的名字,纯数字当作次数
+    pub fn from_name(name: &str) -> Option<Self> {
+        if let Ok(n) = name.parse::<u32>() {
+            return Some(if n == 1 { Self::Once } else { Self::Times(n) });
+        }
+        Some(match name.to_ascii_lowercase().as_str() {
+            "once" => Self::Once,
+            "forever" | "loop" => Self::Forever,
+            "pingpong" | "ping-pong" => Self::PingPong,
+            "hold" => Self::Hold,
+            _ => return None,
+        })
+    }
+}
+
+/// 到头时的动作
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AtEnd {
+    /// 从头开始
+    Rewind,
+    /// 掉头,方向已经翻转过了
+    Reverse,
+    /// 停在端点
+    Hold,
+    Stop,
+}
+
+/// 循环策略加上当前进度,每个播放源各自一份
+#[derive(Debug, Clone)]
+pub struct LoopState {
+    mode: LoopMode,
+    passes: u32,
+    reverse: bool,
+}
+
+impl LoopState {
+    pub fn new(mode: LoopMode) -> Self {
+        Self { mode, passes: 0, reverse: false }
+    }
+
+    pub fn reset(&mut self) {
+        self.passes = 0;
+        self.reverse = false;
+    }
+
+    /// 是否正在倒着播(只有 PingPong 会)
+    #[inline]
+    pub fn reverse(&self) -> bool { self.reverse }
+
+    /// Hold 模式已经到过尾
+    #[inline]
+    pub fn holding(&self) -> bool { self.mode == LoopMode::Hold && self.passes > 0 }
+
+    /// 走完一遍(正向到尾或者反向到头)时调用
+    pub fn on_end(&mut self) -> AtEnd {
+        // 停在最后一帧时每一轮都会走到这里,只记第一次
+        if self.holding() { return AtEnd::Hold }
+        self.passes = self.passes.saturating_add(1);
+        match self.mode {
+            LoopMode::Once => AtEnd::Stop,
+            LoopMode::Times(n) if self.passes >= n => AtEnd::Stop,
+            LoopMode::Times(_) | LoopMode::Forever => AtEnd::Rewind,
+            LoopMode::PingPong => {
+                self.reverse = !self.reverse;
+                AtEnd::Reverse
+            }
+            LoopMode::Hold => AtEnd::Hold,
+        }
+    }
+
+    /// 按帧编号的播放源用:当前是 index,共 len 帧,返回下一帧,None 为播完
+    pub fn step(&mut self, index: usize, len: usize) -> Option<usize> {
+        if len == 0 { return None }
+        let next = if self.reverse { index.checked_sub(1) } else { Some(index + 1).filter(|&i| i < len) };
+        if next.is_some() { return next }
+
+        match self.on_end() {
+            AtEnd::Rewind => Some(0),
+            // 端点已经显示过了,掉头后从它旁边那帧开始
+            AtEnd::Reverse if self.reverse => Some(len.saturating_sub(2)),
+            AtEnd::Reverse => Some(1.min(len - 1)),
+            AtEnd::Hold => Some(index),
+            AtEnd::Stop => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 从第 0 帧开始走 n 步,记下每一步的结果
+    fn run(mode: LoopMode, len: usize, n: usize) -> Vec<Option<usize>> {
+        let mut state = LoopState::new(mode);
+        let mut index = 0;
+        let mut out = Vec::new();
+        for _ in 0..n {
+            let next = state.step(index, len);
+            out.push(next);
+            match next {
+                Some(i) => index = i,
+                None => break,
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn once_stops_at_end() {
+        assert_eq!(run(LoopMode::Once, 3, 5), [Some(1), Some(2), None]);
+    }
+
+    #[test]
+    fn times_rewinds_then_stops() {
+        assert_eq!(run(LoopMode::Times(2), 3, 8), [Some(1), Some(2), Some(0), Some(1), Some(2), None]);
+    }
+
+    #[test]
+    fn forever_keeps_rewinding() {
+        let steps = run(LoopMode::Forever, 3, 9);
+        assert_eq!(steps, [1, 2, 0, 1, 2, 0, 1, 2, 0].map(Some));
+    }
+
+    #[test]
+    fn pingpong_turns_around_without_repeating_endpoints() {
+        let steps = run(LoopMode::PingPong, 4, 9);
+        assert_eq!(steps, [1, 2, 3, 2, 1, 0, 1, 2, 3].map(Some));
+    }
+
+    #[test]
+    fn pingpong_single_frame() {
+        assert_eq!(run(LoopMode::PingPong, 1, 3), [Some(0); 3]);
+    }
+
+    #[test]
+    fn hold_stays_on_last_frame() {
+        let mut state = LoopState::new(LoopMode::Hold);
+        assert_eq!(state.step(0, 3), Some(1));
+        assert_eq!(state.step(1, 3), Some(2));
+        assert!(!state.holding());
+        for _ in 0..3 {
+            assert_eq!(state.step(2, 3), Some(2));
+            assert!(state.holding());
+        }
+        assert_eq!(state.passes, 1);
+    }
+}
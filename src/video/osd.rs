@@ -0,0 +1,116 @@
+use alloc::format;
+use alloc::string::String;
+use uefi::proto::console::gop::BltPixel;
+use crate::clock;
+use crate::graphics::font;
+use crate::graphics::overlay::{Compositor, PlaneId};
+use crate::video::control::Control;
+
+// 边距和进度条,逻辑像素,面板高度跟着字体算
+const PAD: usize = 8;
+const BAR_HEIGHT: usize = 6;
+// 进度条上下离字多远
+const BAR_GAP: (usize, usize) = (6, 4);
+// 按键后显示多久
+const OSD_TIMEOUT_NS: u64 = 3_000_000_000;
+// 半透明,底下的视频还能看见
+const OSD_ALPHA: u8 = 208;
+
+const BG: BltPixel = BltPixel::new(24, 24, 24);
+const FG: BltPixel = BltPixel::new(230, 230, 230);
+const BAR_BG: BltPixel = BltPixel::new(80, 80, 80);
+const BAR_FG: BltPixel = BltPixel::new(0, 160, 255);
+const ACCENT: BltPixel = BltPixel::new(255, 200, 0);
+
+/// 播放信息面板,贴着逻辑屏幕底边,只在 BSP 上用
+pub struct Osd {
+    plane: PlaneId,
+    title: String,
+    total_frames: usize,
+    fps: usize,
+    visible_until: u64,
+    // 上次画的内容,没变就不重画
+    drawn: Option<Snapshot>,
+}
+
+// 决定面板内容的全部状态
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct Snapshot {
+    filled: usize,
+    second: usize,
+    paused: bool,
+    speed: u64,
+}
+
+impl Osd {
+    pub fn new(compositor: &mut Compositor, title: &str, total_frames: usize, fps: usize) -> Self {
+        let (lw, lh) = compositor.logical_size();
+        // 标题一行、进度条、时间码一行
+        let ch = font::cell().1;
+        let height = (PAD + ch + BAR_GAP.0 + BAR_HEIGHT + BAR_GAP.1 + ch + PAD).min(lh);
+        let plane = compositor.add((0, lh - height, lw, height), OSD_ALPHA, None);
+        Self {
+            plane,
+            title: String::from(title),
+            total_frames: total_frames.max(1),
+            fps: fps.max(1),
+            visible_until: 0,
+            drawn: None,
+        }
+    }
+
+    /// 有按键时调用,显示一段时间后自动隐藏
+    pub fn poke(&mut self) {
+        self.visible_until = clock::now_ns() + OSD_TIMEOUT_NS;
+    }
+
+    /// 每轮在 compositor.sync 之前调用
+    pub fn update(&mut self, compositor: &Compositor, control: &Control) {
+        // 暂停时一直显示
+        let visible = control.paused || clock::now_ns() < self.visible_until;
+        compositor.show(self.plane, visible);
+        if !visible { return }
+
+        let (lw, _) = compositor.logical_size();
+        let bar_w = lw.saturating_sub(2 * PAD);
+        let frame = control.frame();
+        let snapshot = Snapshot {
+            filled: bar_w * (frame + 1) / self.total_frames,
+            second: frame / self.fps,
+            paused: control.paused,
+            speed: control.speed_percent(),
+        };
+        if self.drawn == Some(snapshot) { return }
+        self.drawn = Some(snapshot);
+
+        let total = self.total_frames / self.fps;
+        compositor.draw(self.plane, |c| {
+            let (w, _) = c.size();
+            c.clear(BG);
+
+            // 第一行:标题,右边是状态
+            let s = snapshot;
+            let state = if s.paused { String::from("PAUSED") } else { format!("{}.{:02}x", s.speed / 100, s.speed % 100) };
+            let (cw, ch) = font::cell();
+            let state_x = w.saturating_sub(PAD + state.len() * cw);
+            let title_max = state_x.saturating_sub(2 * PAD) / cw;
+            c.text(PAD, PAD, font::clip(&self.title, title_max), FG);
+            c.text(state_x, PAD, &state, if s.paused { ACCENT } else { FG });
+
+            // 第二行:进度条
+            let bar_y = PAD + ch + BAR_GAP.0;
+            c.fill(PAD, bar_y, bar_w, BAR_HEIGHT, BAR_BG);
+            c.fill(PAD, bar_y, s.filled, BAR_HEIGHT, BAR_FG);
+
+            // 第三行:时间码
+            let tc = format!("{} / {}", timecode(s.second), timecode(total));
+            c.text(PAD, bar_y + BAR_HEIGHT + BAR_GAP.1, &tc, FG);
+        });
+    }
+}
+
+// 超过一小时才带小时
+fn timecode(seconds: usize) -> String {
+    let (h, m, s) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+    if h > 0 { format!("{}:{:02}:{:02}", h, m, s) } else { format!("{:02}:{:02}", m, s) }
+}
@@ -0,0 +1,72 @@
+use crate::clock;
+
+// 落后超过这么多就不追了,从当前时刻重新排,免得卡一下之后狂跳帧
+const RESYNC_NS: u64 = 1_000_000_000;
+
+/// 帧调度,只在 BSP 上用
+/// 内容时间线按视频帧率走,显示时刻对齐到屏幕刷新周期(EDID 给了的话)
+/// 画得慢就跳帧追时间线,画得快就原地等
+pub struct Pacer {
+    frame_ns: u64,
+    // 屏幕刷新周期,拿不到 vblank 只能对齐节奏,相位未知
+    refresh_ns: Option<u64>,
+    start_ns: u64,
+    // 下一个内容帧应该出现的时刻
+    due_ns: u64,
+    /// 追时间线跳过的帧数
+    pub dropped: u64,
+    /// 同一帧重复显示的次数(慢放、刷新率高于帧率)
+    pub repeated: u64,
+    /// 最近一帧画完后还剩的时间
+    pub slack_ns: u64,
+}
+
+impl Pacer {
+    /// refresh_mhz 为屏幕刷新率(毫赫兹)
+    pub fn new(fps: usize, refresh_mhz: Option<u32>) -> Self {
+        let frame_ns = 1_000_000_000 / fps.max(1) as u64;
+        let refresh_ns = refresh_mhz.filter(|&r| r > 0).map(|r| 1_000_000_000_000 / r as u64);
+        let start_ns = clock::now_ns();
+        Self { frame_ns, refresh_ns, start_ns, due_ns: start_ns + frame_ns, dropped: 0, repeated: 0, slack_ns: 0 }
+    }
+
+    /// 当前帧画完后调用,等到下一帧该上屏的时刻
+    /// 返回内容时间线走过了几帧,至少为 1,大于 1 说明跳了帧
+    pub fn wait_next(&mut self) -> u64 {
+        let mut now = clock::now_ns();
+        if now > self.due_ns + RESYNC_NS {
+            self.due_ns = now;
+        }
+
+        let target = self.align(self.due_ns);
+        self.slack_ns = target.saturating_sub(now);
+        while now < target {
+            core::hint::spin_loop();
+            now = clock::now_ns();
+        }
+
+        let elapsed = 1 + (now - self.due_ns) / self.frame_ns;
+        self.due_ns += elapsed * self.frame_ns;
+        self.dropped += elapsed - 1;
+        elapsed
+    }
+
+    /// 记录这一轮实际显示的帧,和上一轮一样就算重复
+    /// still 为暂停或者停在最后一帧,这时本来就该不动,不算
+    #[inline]
+    pub fn note(&mut self, prev: usize, next: usize, still: bool) {
+        if prev == next && !still { self.repeated += 1 }
+    }
+
+    // 往后对齐到刷新周期的整数倍
+    #[inline]
+    fn align(&self, t: u64) -> u64 {
+        match self.refresh_ns {
+            Some(period) => {
+                let since = t.saturating_sub(self.start_ns);
+                self.start_ns + since.div_ceil(period) * period
+            }
+            None => t,
+        }
+    }
+}
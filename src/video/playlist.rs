@@ -0,0 +1,132 @@
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use crate::error::{NyaStatus, Result};
+use crate::fs::{join, to_path, Fs};
+use crate::graphics::scale::ScaleMode;
+use crate::net::is_tftp;
+use crate::video::looping::LoopMode;
+
+// 目录播放时认的扩展名
+const VIDEO_EXT: &str = ".qois";
+// m3u 里的自定义指令,作用于下一条路径
+const LOOP_TAG: &str = "#PLAYER-LOOP:";
+const SCALE_TAG: &str = "#PLAYER-SCALE:";
+
+/// 播放列表里的一项
+#[derive(Debug, Clone)]
+pub struct Entry {
+    /// 相对卷根目录的路径
+    pub path: String,
+    /// 循环方式,None 跟随全局配置
+    pub looping: Option<LoopMode>,
+    /// 覆盖全局的缩放方式
+    pub scale: Option<ScaleMode>,
+}
+
+impl Entry {
+    /// 文件名,不带目录
+    pub fn title(&self) -> &str {
+        self.path.rsplit(['\\', '/']).next().unwrap_or(&self.path)
+    }
+}
+
+pub struct Playlist {
+    entries: Vec<Entry>,
+    current: usize,
+}
+
+impl Playlist {
+    /// source 可以是单个视频、目录或者 .m3u/.m3u8 列表,tftp: 开头的只能是单个视频
+    /// 多项时没写循环方式的默认播一遍,只有一项时跟随全局配置
+    pub fn load(fs: &mut Fs, source: &str) -> Result<Self> {
+        let mut entries = if is_tftp(source) {
+            alloc::vec![Entry { path: source.to_string(), looping: None, scale: None }]
+        } else if is_playlist(source) {
+            Self::parse_m3u(fs, source)?
+        } else if fs.is_dir(&to_path(source)?)? {
+            Self::scan_dir(fs, source)?
+        } else {
+            alloc::vec![Entry { path: source.to_string(), looping: None, scale: None }]
+        };
+
+        if entries.is_empty() {
+            return Err(NyaStatus::_Debug(format!("playlist {} has no playable entry", source)));
+        }
+        if entries.len() > 1 {
+            entries.iter_mut().filter(|e| e.looping.is_none()).for_each(|e| e.looping = Some(LoopMode::Once));
+        }
+        Ok(Self { entries, current: 0 })
+    }
+
+    #[inline]
+    pub fn current(&self) -> Option<&Entry> { self.entries.get(self.current) }
+
+    /// 切到下一项,到头了 current 返回 None
+    pub fn next(&mut self) { self.current = (self.current + 1).min(self.entries.len()) }
+
+    /// 切到上一项,已经是第一项就重播第一项
+    pub fn prev(&mut self) { self.current = self.current.saturating_sub(1) }
+
+    // 目录里所有视频按文件名排序,大小写不敏感
+    fn scan_dir(fs: &mut Fs, dir: &str) -> Result<Vec<Entry>> {
+        let mut names: Vec<String> = fs.read_dir(&to_path(dir)?)?
+            .into_iter()
+            .filter(|n| is_video(n))
+            .collect();
+        names.sort_by_key(|n| n.to_ascii_lowercase());
+        Ok(names.into_iter().map(|n| Entry { path: join(dir, &n), looping: None, scale: None }).collect())
+    }
+
+    // 一行一个路径,相对路径以列表所在目录为准,# 开头是注释或指令
+    fn parse_m3u(fs: &mut Fs, path: &str) -> Result<Vec<Entry>> {
+        let data = fs.read_file(&to_path(path)?)?;
+        let text = core::str::from_utf8(&data)
+            .map_err(|_| NyaStatus::_Debug(format!("{}: not valid UTF-8", path)))?;
+        let base = parent(path);
+
+        let mut entries = Vec::new();
+        let (mut looping, mut scale) = (None, None);
+        for (no, line) in text.trim_start_matches('\u{feff}').lines().enumerate() {
+            let line = line.trim();
+            if let Some(mode) = line.strip_prefix(LOOP_TAG) {
+                looping = Some(LoopMode::from_name(mode.trim())
+                    .ok_or_else(|| NyaStatus::_Debug(format!("{}:{}: unknown loop mode '{}'", path, no + 1, mode)))?);
+            } else if let Some(name) = line.strip_prefix(SCALE_TAG) {
+                scale = Some(ScaleMode::from_name(name.trim())
+                    .ok_or_else(|| NyaStatus::_Debug(format!("{}:{}: unknown scale mode '{}'", path, no + 1, name)))?);
+            } else if !line.is_empty() && !line.starts_with('#') {
+                // 带卷前缀的原样用,斜杠开头的相对列表所在卷的根目录
+                let path = if line.contains(':') {
+                    line.to_string()
+                } else if line.starts_with(['\\', '/']) {
+                    format!("{}{}", volume(path), line.trim_start_matches(['\\', '/']))
+                } else {
+                    join(base, line)
+                };
+                entries.push(Entry { path, looping: looping.take(), scale: scale.take() });
+            }
+        }
+        Ok(entries)
+    }
+}
+
+/// 按扩展名判断能不能直接播
+pub fn is_video(name: &str) -> bool {
+    name.to_ascii_lowercase().ends_with(VIDEO_EXT)
+}
+
+/// .m3u/.m3u8 播放列表
+pub fn is_playlist(name: &str) -> bool {
+    let lower = name.to_ascii_lowercase();
+    lower.ends_with(".m3u") || lower.ends_with(".m3u8")
+}
+
+// fsN: 这样的卷前缀,没有就是空串
+fn volume(path: &str) -> &str {
+    path.find(':').map_or("", |i| &path[..=i])
+}
+
+fn parent(path: &str) -> &str {
+    path.rfind(['\\', '/']).map_or("", |i| &path[..i])
+}